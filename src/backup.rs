@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use structopt::StructOpt;
+
+use sonar_as_code::secret_string::SecretString;
+use sonar_as_code::server_backup::backup as backup_server;
+use sonar_as_code::sonar_api::{SonarApi, SonarApiError};
+
+#[derive(StructOpt, Debug)]
+pub struct BackupCmd {
+    #[structopt(name = "directory", short = "d", long = "directory", default_value = ".", about = "directory where the timestamped backup directory is created")]
+    directory: String,
+
+    #[structopt(name = "sonarUrl", short = "s", about = "URL of SonarQube")]
+    url: String,
+
+    #[structopt(name = "username", short = "u", default_value = "admin", about = "Username of the administrator")]
+    username: String,
+
+    #[structopt(name = "password", short = "p", default_value = "admin", about = "Password of the administrator")]
+    password: SecretString,
+
+    #[structopt(name = "nbAttempts", short = "a", default_value = "600", about = "Number of attemps to connect to the API (1sec between attempts)")]
+    number_attempts: usize,
+}
+
+// Writes the configuration and the quality profiles of SonarQube in a new directory, its path is printed.
+pub fn backup(cmd: BackupCmd) -> Result<(), SonarApiError> {
+    let sonar_api = SonarApi::new(cmd.url.to_string(), cmd.username.to_string(), cmd.password.clone(), cmd.number_attempts);
+
+    sonar_api.wait_ready()?;
+
+    let directory = backup_server(&sonar_api, Path::new(&cmd.directory))?;
+
+    println!("{}", directory.display());
+
+    return Ok(());
+}
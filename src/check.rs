@@ -0,0 +1,103 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use structopt::StructOpt;
+
+use configuration_options::ConfigurationOptions;
+use sonar_as_code::config_file_model::ConfigurationFile;
+use sonar_as_code::reconcile::resources;
+use sonar_as_code::report::{Action, ResourceKind};
+use sonar_as_code::resource::Resource;
+use sonar_as_code::secret_string::SecretString;
+use sonar_as_code::sonar_api::{SonarApi, SonarApiError};
+use sonar_as_code::sonar_client::SonarClient;
+use sonar_as_code::sonar_snapshot::SonarSnapshot;
+
+#[derive(StructOpt, Debug)]
+pub struct CheckCmd {
+    #[structopt(flatten)]
+    configuration: ConfigurationOptions,
+
+    #[structopt(name = "sonarUrl", short = "s", about = "URL of SonarQube")]
+    url: String,
+
+    #[structopt(name = "username", short = "u", default_value = "admin", about = "Username of the administrator")]
+    username: String,
+
+    #[structopt(name = "password", short = "p", default_value = "admin", about = "Password of the administrator")]
+    password: SecretString,
+
+    #[structopt(name = "nbAttempts", short = "a", default_value = "600", about = "Number of attemps to connect to the API (1sec between attempts)")]
+    number_attempts: usize,
+
+    #[structopt(name = "prune", long = "prune", about = "report resources that are not declared (groups) as drifts")]
+    prune: bool,
+}
+
+// Difference between the live server and the configuration file.
+pub struct Drift {
+    pub kind: ResourceKind,
+    pub name: String,
+    pub description: String,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}: {}", self.kind, self.name, self.description)
+    }
+}
+
+// Compares the live server to the configuration file without changing anything, returns whether they are in sync.
+pub fn check(cmd: CheckCmd) -> Result<bool, SonarApiError> {
+    let config_file: ConfigurationFile = cmd.configuration.load().map_err(SonarApiError::new)?;
+
+    let sonar_api = SonarApi::new(cmd.url.to_string(), cmd.username.to_string(), cmd.password.clone(), cmd.number_attempts);
+
+    sonar_api.wait_ready()?;
+
+    let resources = resources(config_file, &sonar_api, cmd.configuration.env_file.as_ref(), cmd.prune)?;
+    let drifts = detect_drifts(&SonarSnapshot::fetch(&sonar_api)?, &resources)?;
+
+    if drifts.is_empty() {
+        println!("SonarQube is in sync with the configuration.");
+    } else {
+        println!("{} drift(s) detected:", drifts.len());
+
+        for drift in &drifts {
+            println!("  {}", drift);
+        }
+    }
+
+    return Ok(drifts.is_empty());
+}
+
+fn detect_drifts(client: &dyn SonarClient, resources: &[Box<dyn Resource>]) -> Result<Vec<Drift>, SonarApiError> {
+    let mut drifts = Vec::new();
+
+    // resources that don't exist yet, the ones depending on them cannot be read
+    let mut missing: BTreeSet<(ResourceKind, String)> = BTreeSet::new();
+
+    for resource in resources {
+        if resource.depends_on().iter().any(|dependency| missing.contains(dependency)) {
+            missing.insert((resource.kind(), resource.name()));
+            continue;
+        }
+
+        let changes = resource.diff(client)?;
+
+        if changes.iter().any(|change| change.action == Action::Created) {
+            missing.insert((resource.kind(), resource.name()));
+        }
+
+        for change in changes {
+            if !change.drift {
+                debug!("The {} [{}] cannot be checked: {}.", resource.kind(), resource.name(), change);
+                continue;
+            }
+
+            drifts.push(Drift { kind: resource.kind(), name: resource.name(), description: change.description });
+        }
+    }
+
+    return Ok(drifts);
+}
@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io::{stdin, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use config_file_model::ConfigurationFile;
+use config_file_overlay;
+
+const STDIN_FILE: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigurationFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigurationFormat {
+    pub fn from_path(path: &str) -> Option<ConfigurationFormat> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+
+        match extension.as_str() {
+            "yaml" | "yml" => Some(ConfigurationFormat::Yaml),
+            "json" => Some(ConfigurationFormat::Json),
+            "toml" => Some(ConfigurationFormat::Toml),
+            _ => None,
+        }
+    }
+
+    // Every format is parsed into a YAML value, overlays are then applied the same way whatever the format.
+    fn parse(&self, content: &str) -> Result<serde_yaml::Value, String> {
+        match self {
+            ConfigurationFormat::Yaml => serde_yaml::from_str(content).map_err(|error| error.to_string()),
+            ConfigurationFormat::Json => serde_json::from_str(content).map_err(|error| error.to_string()),
+            ConfigurationFormat::Toml => toml::from_str(content).map_err(|error| error.to_string()),
+        }
+    }
+}
+
+impl FromStr for ConfigurationFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "yaml" | "yml" => Ok(ConfigurationFormat::Yaml),
+            "json" => Ok(ConfigurationFormat::Json),
+            "toml" => Ok(ConfigurationFormat::Toml),
+            _ => Err(format!("Unsupported format [{}], expecting yaml, json or toml.", value)),
+        }
+    }
+}
+
+// Reads the configuration file (- for the standard input), then applies the overlay of the environment declared in it
+// (if any) and the overlay files in order.
+pub fn load_configuration(file: &str, format: Option<ConfigurationFormat>, environment: Option<&String>, overlays: &[String]) -> Result<ConfigurationFile, String> {
+    debug!("Loading configuration file {:?}", file);
+
+    let mut configuration = read_file(file, format)?;
+
+    if let Some(overlay) = config_file_overlay::take_environment(&mut configuration, environment)? {
+        debug!("Apply overlay of environment {:?}", environment);
+
+        config_file_overlay::apply_overlay(&mut configuration, overlay)?;
+    }
+
+    for overlay_file in overlays {
+        debug!("Apply overlay file {:?}", overlay_file);
+
+        // overlays can have a different format than the configuration file, the specified format is only a fallback
+        let overlay_format = ConfigurationFormat::from_path(overlay_file).or(format);
+
+        config_file_overlay::apply_overlay(&mut configuration, read_file(overlay_file, overlay_format)?)?;
+    }
+
+    return serde_yaml::from_value(configuration)
+        .map_err(|error| format!("Invalid configuration file [{}]: {}", file, error));
+}
+
+fn read_file(path: &str, format: Option<ConfigurationFormat>) -> Result<serde_yaml::Value, String> {
+    let mut file_content = String::new();
+
+    if path == STDIN_FILE {
+        stdin().read_to_string(&mut file_content)
+            .map_err(|error| format!("Cannot read configuration from the standard input: {}", error))?;
+    } else {
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut file_content))
+            .map_err(|error| format!("Cannot read configuration file [{}]: {}", path, error))?;
+    }
+
+    let directory = if path == STDIN_FILE { None } else { Path::new(path).parent() };
+
+    return parse_configuration(&file_content, path, format, directory);
+}
+
+// Parses the content of the configuration file, in the specified format or the one of its extension (YAML by default).
+// Files it references are made relative to the directory, if any.
+pub fn parse_configuration(content: &str, path: &str, format: Option<ConfigurationFormat>, directory: Option<&Path>) -> Result<serde_yaml::Value, String> {
+    let format = format
+        .or_else(|| ConfigurationFormat::from_path(path))
+        .unwrap_or(ConfigurationFormat::Yaml);
+
+    let mut configuration = format.parse(content)
+        .map_err(|error| format!("Error while parsing configuration file [{}] as {:?}: {}", path, format, error))?;
+
+    if let Some(directory) = directory {
+        resolve_files(&mut configuration, directory);
+    }
+
+    return Ok(configuration);
+}
+
+// Files referenced by the configuration (quality profiles) are relative to it, the ones of environments included.
+fn resolve_files(configuration: &mut serde_yaml::Value, directory: &Path) {
+    if let Some(profiles) = configuration.get_mut("qualityProfiles").and_then(serde_yaml::Value::as_sequence_mut) {
+        for profile in profiles {
+            if let Some(file) = profile.get_mut("file") {
+                if let Some(relative) = file.as_str().filter(|file| Path::new(file).is_relative()) {
+                    *file = serde_yaml::Value::String(directory.join(relative).to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+
+    if let Some(environments) = configuration.get_mut("environments").and_then(serde_yaml::Value::as_mapping_mut) {
+        for (_, environment) in environments.iter_mut() {
+            resolve_files(environment, directory);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+    fn load(content: &str, extension: &str, format: Option<ConfigurationFormat>) -> Result<ConfigurationFile, String> {
+        let file = env::temp_dir().join(format!("sonar-as-code-loader-{}-{}.{}", std::process::id(), NEXT_FILE.fetch_add(1, Ordering::SeqCst), extension));
+        fs::write(&file, content).unwrap();
+
+        let configuration = load_configuration(file.to_str().unwrap(), format, None, &[]);
+        fs::remove_file(&file).unwrap();
+
+        return configuration;
+    }
+
+    fn property_names(configuration: &ConfigurationFile) -> Vec<String> {
+        configuration.properties.iter().flatten().map(|property| property.name.to_string()).collect()
+    }
+
+    #[test]
+    fn detects_the_format_from_the_extension() {
+        assert_eq!(ConfigurationFormat::from_path("sonar.yaml"), Some(ConfigurationFormat::Yaml));
+        assert_eq!(ConfigurationFormat::from_path("sonar.YML"), Some(ConfigurationFormat::Yaml));
+        assert_eq!(ConfigurationFormat::from_path("conf/sonar.json"), Some(ConfigurationFormat::Json));
+        assert_eq!(ConfigurationFormat::from_path("sonar.toml"), Some(ConfigurationFormat::Toml));
+        assert_eq!(ConfigurationFormat::from_path("sonar.txt"), None);
+        assert_eq!(ConfigurationFormat::from_path("-"), None);
+    }
+
+    #[test]
+    fn parses_the_format_option() {
+        assert_eq!("JSON".parse::<ConfigurationFormat>(), Ok(ConfigurationFormat::Json));
+        assert_eq!("yml".parse::<ConfigurationFormat>(), Ok(ConfigurationFormat::Yaml));
+        assert_eq!("xml".parse::<ConfigurationFormat>(), Err("Unsupported format [xml], expecting yaml, json or toml.".to_string()));
+    }
+
+    #[test]
+    fn loads_every_format() {
+        let yaml = load("properties:\n  - name: sonar.core.serverBaseURL\n    value: https://sonar\n", "yaml", None).unwrap();
+        let json = load(r#"{"properties": [{"name": "sonar.core.serverBaseURL", "value": "https://sonar"}]}"#, "json", None).unwrap();
+        let toml = load("[[properties]]\nname = \"sonar.core.serverBaseURL\"\nvalue = \"https://sonar\"\n", "toml", None).unwrap();
+
+        assert_eq!(property_names(&yaml), vec!["sonar.core.serverBaseURL"]);
+        assert_eq!(property_names(&json), vec!["sonar.core.serverBaseURL"]);
+        assert_eq!(property_names(&toml), vec!["sonar.core.serverBaseURL"]);
+    }
+
+    #[test]
+    fn the_format_option_overrides_the_extension() {
+        let content = "[[properties]]\nname = \"sonar.core.serverBaseURL\"\nvalue = \"https://sonar\"\n";
+
+        assert_eq!(property_names(&load(content, "txt", Some(ConfigurationFormat::Toml)).unwrap()), vec!["sonar.core.serverBaseURL"]);
+        assert!(load(content, "yaml", None).is_err());
+    }
+
+    #[test]
+    fn defaults_to_yaml() {
+        let configuration = parse_configuration("groups:\n  - name: developers\n", "-", None, None).unwrap();
+
+        assert_eq!(configuration["groups"][0]["name"].as_str(), Some("developers"));
+    }
+
+    #[test]
+    fn reports_parsing_errors() {
+        let error = parse_configuration("{", "sonar.json", None, None).unwrap_err();
+
+        assert!(error.starts_with("Error while parsing configuration file [sonar.json] as Json: "), "{}", error);
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let error = load_configuration("missing/sonar.yaml", None, None, &[]).err().unwrap();
+
+        assert!(error.starts_with("Cannot read configuration file [missing/sonar.yaml]: "), "{}", error);
+    }
+
+    #[test]
+    fn quality_profiles_are_relative_to_the_configuration_file() {
+        let content = "qualityProfiles:\n  - file: strict.xml\n  - file: /backups/safe.xml\nenvironments:\n  prod:\n    qualityProfiles:\n      - file: prod.xml\n";
+        let configuration = parse_configuration(content, "conf/sonar.yaml", None, Some(Path::new("conf"))).unwrap();
+
+        assert_eq!(configuration["qualityProfiles"][0]["file"].as_str(), Some(Path::new("conf").join("strict.xml").to_str().unwrap()));
+        assert_eq!(configuration["qualityProfiles"][1]["file"].as_str(), Some("/backups/safe.xml"));
+        assert_eq!(configuration["environments"]["prod"]["qualityProfiles"][0]["file"].as_str(), Some(Path::new("conf").join("prod.xml").to_str().unwrap()));
+    }
+}
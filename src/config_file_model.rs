@@ -1,27 +1,126 @@
+use std::collections::BTreeMap;
+
+use secret_string::SecretString;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfigurationFile {
     pub properties: Option<Vec<Property>>,
     pub users: Option<Vec<User>>,
-    pub groups: Option<Vec<Group>>
+    pub groups: Option<Vec<Group>>,
+
+    #[serde(rename = "customRules")]
+    pub custom_rules: Option<Vec<CustomRule>>,
+
+    #[serde(rename = "qualityProfiles")]
+    pub quality_profiles: Option<Vec<QualityProfile>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Property {
     pub name: String,
     pub value: String,
+    pub sensitive: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub name: String,
     pub login: String,
-    pub password: String,
+    pub password: Option<SecretString>,
     pub groups: Option<Vec<String>>,
+
+    // users authenticated by SonarQube are local, the others are authenticated by an external provider (SAML, LDAP, GitHub...)
+    pub local: Option<bool>,
+
+    #[serde(rename = "externalIdentity")]
+    pub external_identity: Option<String>,
+
+    #[serde(rename = "externalProvider")]
+    pub external_provider: Option<String>,
+
+    #[serde(rename = "passwordPolicy")]
+    pub password_policy: Option<PasswordPolicy>,
+
+    pub state: Option<UserState>,
+
+    // personal data of a deactivated user is removed
+    pub anonymize: Option<bool>,
+
+    pub email: Option<String>,
+
+    // accounts used in the SCM, issues are assigned to the user by commit author
+    #[serde(rename = "scmAccounts")]
+    pub scm_accounts: Option<Vec<String>>,
+
+    pub notifications: Option<Vec<Notification>>,
 }
 
+// Subscription to a notification, global when the project is not specified.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Notification {
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub project: Option<String>,
+    pub channel: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UserState {
+    #[default]
+    Active,
+    Deactivated,
+}
+
+// When the password of a local user is set.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PasswordPolicy {
+    // the password is set at every run
+    #[default]
+    Always,
+
+    // the password is only set when the user is created, it can then be changed by the user
+    CreateOnly,
+}
+
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Group {
     pub name: String,
     pub description: String,
     pub permissions: Vec<String>,
+    pub members: Option<Vec<String>>,
+}
+
+// Rule created from a rule template (see /api/rules/create), e.g. a regular expression or an XPath rule. Its key is the
+// custom key in the repository of the template, e.g. java:no-todo for the template java:S124.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomRule {
+    #[serde(rename = "templateKey")]
+    pub template_key: String,
+
+    #[serde(rename = "customKey")]
+    pub custom_key: String,
+
+    pub name: String,
+
+    // the ones of the template when they are not specified
+    pub severity: Option<String>,
+
+    #[serde(rename = "type")]
+    pub rule_type: Option<String>,
+
+    #[serde(rename = "markdownDescription")]
+    pub markdown_description: String,
+
+    // parameters of the template, e.g. the regular expression
+    pub params: Option<BTreeMap<String, String>>,
+}
+
+// Quality profile restored from its XML backup (see /api/qualityprofiles/backup).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QualityProfile {
+    // path of the backup, relative to the configuration file declaring it
+    pub file: String,
 }
@@ -0,0 +1,248 @@
+use serde_yaml::{Mapping, Value};
+
+const ENVIRONMENTS_KEY: &str = "environments";
+const PATCH_KEY: &str = "$patch";
+
+// Lists of the configuration file whose entries are identified by a key, overlay entries are matched on this key.
+const KEYED_SECTIONS: [(&str, &str); 5] = [("properties", "name"), ("users", "login"), ("groups", "name"), ("customRules", "customKey"), ("qualityProfiles", "file")];
+
+#[derive(Debug, PartialEq)]
+enum PatchOperation {
+    Merge,
+    Replace,
+    Delete,
+}
+
+// Removes the environments declared in the configuration and returns the overlay of the specified one (if any).
+pub fn take_environment(configuration: &mut Value, environment: Option<&String>) -> Result<Option<Value>, String> {
+    let environments = match configuration.as_mapping_mut() {
+        Some(mapping) => mapping.remove(&Value::String(ENVIRONMENTS_KEY.to_string())),
+        None => None,
+    };
+
+    let environment = match environment {
+        Some(environment) => environment,
+        None => return Ok(None),
+    };
+
+    let overlay = environments
+        .as_ref()
+        .and_then(|environments| environments.as_mapping())
+        .and_then(|environments| environments.get(&Value::String(environment.to_string())))
+        .ok_or_else(|| format!("The environment [{}] is not defined in the configuration file", environment))?;
+
+    return Ok(Some(overlay.clone()));
+}
+
+// Patches the base configuration with the overlay. Entries of keyed lists (properties, users, groups, custom rules,
+// quality profiles) are merged with the base entry having the same key, appended if there is none, or removed/replaced
+// when "$patch" is "delete"/"replace".
+pub fn apply_overlay(base: &mut Value, overlay: Value) -> Result<(), String> {
+    let overlay = match overlay {
+        Value::Mapping(overlay) => overlay,
+        Value::Null => return Ok(()),
+        _ => return Err("An overlay must be a mapping".to_string()),
+    };
+
+    if !base.is_mapping() {
+        *base = Value::Mapping(Mapping::new());
+    }
+    let base = base.as_mapping_mut().unwrap();
+
+    for (section, overlay_value) in overlay {
+        if section.as_str() == Some(ENVIRONMENTS_KEY) {
+            continue;
+        }
+
+        match KEYED_SECTIONS.iter().find(|(name, _)| section.as_str() == Some(name)) {
+            Some((name, key)) => {
+                let overlay_entries = match overlay_value {
+                    Value::Sequence(entries) => entries,
+                    Value::Null => continue,
+                    _ => return Err(format!("The overlay section [{}] must be a list", name)),
+                };
+
+                let base_value = base.entry(section.clone()).or_insert(Value::Sequence(Vec::new()));
+                if !base_value.is_sequence() {
+                    *base_value = Value::Sequence(Vec::new());
+                }
+
+                merge_entries(base_value.as_sequence_mut().unwrap(), overlay_entries, name, key)?;
+            }
+            None => {
+                match base.get_mut(&section) {
+                    Some(base_value) => merge_values(base_value, overlay_value),
+                    None => {
+                        base.insert(section, overlay_value);
+                    }
+                }
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+fn merge_entries(base_entries: &mut Vec<Value>, overlay_entries: Vec<Value>, section: &str, key: &str) -> Result<(), String> {
+    let key_value = Value::String(key.to_string());
+
+    for overlay_entry in overlay_entries {
+        let mut overlay_entry = match overlay_entry {
+            Value::Mapping(entry) => entry,
+            _ => return Err(format!("Entries of the overlay section [{}] must be mappings", section)),
+        };
+
+        let operation = parse_patch_operation(overlay_entry.remove(&Value::String(PATCH_KEY.to_string())), section)?;
+
+        let entry_key = overlay_entry.get(&key_value)
+            .cloned()
+            .ok_or_else(|| format!("Entries of the overlay section [{}] must define [{}]", section, key))?;
+
+        let position = base_entries.iter()
+            .position(|base_entry| base_entry.as_mapping().and_then(|base_entry| base_entry.get(&key_value)) == Some(&entry_key));
+
+        match (operation, position) {
+            (PatchOperation::Delete, Some(position)) => {
+                debug!("Overlay removes [{:?}] from [{}].", entry_key, section);
+                base_entries.remove(position);
+            }
+            (PatchOperation::Delete, None) => {
+                debug!("Overlay removes [{:?}] from [{}], but it is not defined.", entry_key, section);
+            }
+            (PatchOperation::Replace, Some(position)) => {
+                base_entries[position] = Value::Mapping(overlay_entry);
+            }
+            (PatchOperation::Merge, Some(position)) => {
+                merge_values(&mut base_entries[position], Value::Mapping(overlay_entry));
+            }
+            (_, None) => {
+                base_entries.push(Value::Mapping(overlay_entry));
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+fn merge_values(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, overlay_value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_values(base_value, overlay_value),
+                    None => {
+                        base.insert(key, overlay_value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn parse_patch_operation(value: Option<Value>, section: &str) -> Result<PatchOperation, String> {
+    match value.as_ref().map(|value| value.as_str()) {
+        None => Ok(PatchOperation::Merge),
+        Some(Some("merge")) => Ok(PatchOperation::Merge),
+        Some(Some("replace")) => Ok(PatchOperation::Replace),
+        Some(Some("delete")) => Ok(PatchOperation::Delete),
+        _ => Err(format!("Unsupported [{}] {:?} in the overlay section [{}], expecting merge, replace or delete", PATCH_KEY, value, section)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(content: &str) -> Value {
+        serde_yaml::from_str(content).unwrap()
+    }
+
+    fn overlay(base: &str, overlay: &str) -> Result<Value, String> {
+        let mut base = yaml(base);
+        apply_overlay(&mut base, yaml(overlay))?;
+
+        return Ok(base);
+    }
+
+    const BASE: &str = r#"
+properties:
+  - name: sonar.core.serverBaseURL
+    value: https://sonar
+groups:
+  - name: developers
+    description: Developers
+    permissions: [scan]
+  - name: testers
+    description: Testers
+"#;
+
+    #[test]
+    fn merges_entries_having_the_same_key() {
+        let configuration = overlay(BASE, "groups: [{name: developers, permissions: [scan, user]}]").unwrap();
+
+        assert_eq!(configuration["groups"], yaml("[{name: developers, description: Developers, permissions: [scan, user]}, {name: testers, description: Testers}]"));
+        assert_eq!(configuration["properties"], yaml(BASE)["properties"]);
+    }
+
+    #[test]
+    fn appends_new_entries() {
+        let configuration = overlay(BASE, "properties: [{name: sonar.forceAuthentication, value: 'true'}]").unwrap();
+
+        assert_eq!(configuration["properties"], yaml("[{name: sonar.core.serverBaseURL, value: 'https://sonar'}, {name: sonar.forceAuthentication, value: 'true'}]"));
+    }
+
+    #[test]
+    fn deletes_entries() {
+        let configuration = overlay(BASE, "groups: [{name: testers, $patch: delete}, {name: unknown, $patch: delete}]").unwrap();
+
+        assert_eq!(configuration["groups"], yaml("[{name: developers, description: Developers, permissions: [scan]}]"));
+    }
+
+    #[test]
+    fn replaces_entries() {
+        let configuration = overlay(BASE, "groups: [{name: developers, $patch: replace, permissions: [user]}]").unwrap();
+
+        assert_eq!(configuration["groups"][0], yaml("{name: developers, permissions: [user]}"));
+    }
+
+    #[test]
+    fn rejects_unsupported_patch_operations() {
+        let error = overlay(BASE, "groups: [{name: developers, $patch: upsert}]").unwrap_err();
+
+        assert!(error.starts_with("Unsupported [$patch]"), "{}", error);
+        assert!(error.ends_with("in the overlay section [groups], expecting merge, replace or delete"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_sections_that_are_not_lists() {
+        assert_eq!(overlay(BASE, "groups: {name: developers}"), Err("The overlay section [groups] must be a list".to_string()));
+        assert_eq!(overlay(BASE, "groups: [developers]"), Err("Entries of the overlay section [groups] must be mappings".to_string()));
+        assert_eq!(overlay(BASE, "groups: [{description: Developers}]"), Err("Entries of the overlay section [groups] must define [name]".to_string()));
+    }
+
+    #[test]
+    fn takes_the_overlay_of_the_environment() {
+        let mut configuration = yaml("groups: []\nenvironments:\n  prod:\n    groups: [{name: admins}]\n");
+
+        let environment = take_environment(&mut configuration, Some(&"prod".to_string())).unwrap();
+
+        assert_eq!(environment, Some(yaml("groups: [{name: admins}]")));
+        assert_eq!(configuration, yaml("groups: []"));
+    }
+
+    #[test]
+    fn removes_environments_when_none_is_applied() {
+        let mut configuration = yaml("groups: []\nenvironments:\n  prod:\n    groups: [{name: admins}]\n");
+
+        assert_eq!(take_environment(&mut configuration, None), Ok(None));
+        assert_eq!(configuration, yaml("groups: []"));
+    }
+
+    #[test]
+    fn rejects_unknown_environments() {
+        let mut configuration = yaml("groups: []\nenvironments:\n  prod:\n    groups: [{name: admins}]\n");
+
+        assert_eq!(take_environment(&mut configuration, Some(&"staging".to_string())), Err("The environment [staging] is not defined in the configuration file".to_string()));
+    }
+}
@@ -0,0 +1,28 @@
+use structopt::StructOpt;
+
+use sonar_as_code::config_file_loader::{load_configuration, ConfigurationFormat};
+use sonar_as_code::config_file_model::ConfigurationFile;
+
+#[derive(StructOpt, Debug)]
+pub struct ConfigurationOptions {
+    #[structopt(name = "file", short = "f", about = "file to the configuration file (YAML, JSON or TOML), - for the standard input")]
+    pub file: String,
+
+    #[structopt(name = "format", long = "format", about = "format of the configuration file (yaml, json or toml), detected from the file extension by default")]
+    pub format: Option<ConfigurationFormat>,
+
+    #[structopt(name = "overlay", short = "o", long = "overlay", about = "file to an overlay patching the configuration file, can be repeated")]
+    pub overlays: Vec<String>,
+
+    #[structopt(name = "environment", short = "e", long = "environment", about = "name of the environment (declared in the configuration file) to apply")]
+    pub environment: Option<String>,
+
+    #[structopt(name = "envFile", long = "env-file", about = "file defining variables (KEY=VALUE), environment variables take precedence")]
+    pub env_file: Option<String>,
+}
+
+impl ConfigurationOptions {
+    pub fn load(&self) -> Result<ConfigurationFile, String> {
+        return load_configuration(&self.file, self.format, self.environment.as_ref(), &self.overlays);
+    }
+}
@@ -0,0 +1,127 @@
+use report::{Action, ResourceKind};
+use resource::{Change, Resource};
+use sonar_api::SonarApiError;
+use sonar_api_model::{SonarCustomRule, SonarRule};
+use sonar_client::SonarClient;
+
+// Rule created from a rule template. The template and the type of a rule cannot be updated, the rule is then deleted
+// and created again.
+pub struct CustomRuleResource {
+    rule: SonarCustomRule,
+}
+
+impl CustomRuleResource {
+    pub fn new(rule: SonarCustomRule) -> CustomRuleResource {
+        CustomRuleResource { rule }
+    }
+
+    // Attributes that cannot be updated and differ.
+    fn fixed_differences(&self, current: &SonarRule) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        if current.template_key.as_deref() != Some(self.rule.template_key.as_str()) {
+            differences.push(format!("template is [{}], expecting [{}]", current.template_key.as_deref().unwrap_or_default(), self.rule.template_key));
+        }
+
+        if let Some(rule_type) = self.rule.rule_type.as_ref().filter(|rule_type| current.rule_type.as_ref() != Some(rule_type)) {
+            differences.push(format!("type is [{}], expecting [{}]", current.rule_type.as_deref().unwrap_or_default(), rule_type));
+        }
+
+        return differences;
+    }
+
+    // Attributes that can be updated and differ.
+    fn differences(&self, current: &SonarRule) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        if current.name != self.rule.name {
+            differences.push(format!("name is [{}], expecting [{}]", current.name, self.rule.name));
+        }
+
+        if let Some(severity) = self.rule.severity.as_ref().filter(|severity| current.severity.as_ref() != Some(severity)) {
+            differences.push(format!("severity is [{}], expecting [{}]", current.severity.as_deref().unwrap_or_default(), severity));
+        }
+
+        if current.markdown_description.as_deref().unwrap_or_default() != self.rule.markdown_description {
+            differences.push("description differs".to_string());
+        }
+
+        for (key, value) in &self.rule.params {
+            if current.param(key) != Some(value.as_str()) {
+                differences.push(format!("parameter [{}] is [{}], expecting [{}]", key, current.param(key).unwrap_or_default(), value));
+            }
+        }
+
+        return differences;
+    }
+}
+
+impl Resource for CustomRuleResource {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::CustomRule
+    }
+
+    fn name(&self) -> String {
+        self.rule.key()
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let rule = self.rule.clone();
+
+        let current = match client.get_rule(&rule.key())? {
+            Some(current) if !current.is_removed() => current,
+            _ => return Ok(vec![Change::new(Action::Created, "does not exist".to_string(), move |client| client.create_custom_rule(&rule))]),
+        };
+
+        let fixed_differences = self.fixed_differences(&current);
+        if !fixed_differences.is_empty() {
+            return Ok(vec![Change::new(Action::Updated, fixed_differences.join(", "), move |client| {
+                client.delete_rule(&rule.key())?;
+
+                return client.create_custom_rule(&rule);
+            })]);
+        }
+
+        let differences = self.differences(&current);
+        if !differences.is_empty() {
+            return Ok(vec![Change::new(Action::Updated, differences.join(", "), move |client| client.update_custom_rule(&rule))]);
+        }
+
+        return Ok(vec![]);
+    }
+}
+
+// Custom rule existing on SonarQube but not declared, it's deleted.
+pub struct AbsentCustomRule {
+    key: String,
+}
+
+impl AbsentCustomRule {
+    pub fn new(key: &str) -> AbsentCustomRule {
+        AbsentCustomRule { key: key.to_string() }
+    }
+}
+
+impl Resource for AbsentCustomRule {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::CustomRule
+    }
+
+    fn name(&self) -> String {
+        self.key.to_string()
+    }
+
+    fn is_absent(&self) -> bool {
+        return true;
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        if client.get_rule(&self.key)?.filter(|rule| !rule.is_removed()).is_none() {
+            return Ok(vec![]);
+        }
+
+        let key = self.key.to_string();
+
+        return Ok(vec![Change::new(Action::Deleted, "is not declared".to_string(), move |client| client.delete_rule(&key))]);
+    }
+}
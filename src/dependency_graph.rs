@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use report::ResourceKind;
+use resource::Resource;
+
+// References to resources that are not declared, e.g. a user member of a group that does not exist. They are detected
+// before contacting SonarQube.
+pub fn check_references(resources: &[Box<dyn Resource>]) -> Result<(), String> {
+    let declared: BTreeSet<(ResourceKind, String)> = resources.iter().map(|resource| (resource.kind(), resource.name())).collect();
+
+    let dangling: Vec<String> = resources.iter()
+        .flat_map(|resource| resource.depends_on().into_iter().map(move |dependency| (resource, dependency)))
+        .filter(|(_, dependency)| !declared.contains(dependency))
+        .map(|(resource, (kind, name))| format!("{} [{}] references the {} [{}] that is not declared", resource.kind(), resource.name(), kind, name))
+        .collect();
+
+    if !dangling.is_empty() {
+        return Err(dangling.join(", "));
+    }
+
+    return Ok(());
+}
+
+// Indexes of the resources every resource depends on, references must have been checked.
+pub fn dependency_indexes(resources: &[Box<dyn Resource>]) -> Vec<Vec<usize>> {
+    let indexes: BTreeMap<(ResourceKind, String), usize> = resources.iter().enumerate()
+        .map(|(index, resource)| ((resource.kind(), resource.name()), index))
+        .collect();
+
+    return resources.iter()
+        .map(|resource| resource.depends_on().iter().map(|dependency| indexes[dependency]).collect())
+        .collect();
+}
+
+// Orders the resources so that every resource comes after the ones it depends on, the declaration order is kept
+// otherwise. Resources to delete come last, in the reverse order: dependent resources are deleted first.
+pub fn dependency_order(resources: Vec<Box<dyn Resource>>) -> Result<Vec<Box<dyn Resource>>, String> {
+    check_references(&resources)?;
+
+    let mut dependencies: Vec<usize> = vec![0; resources.len()];
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); resources.len()];
+    for (index, resource_dependencies) in dependency_indexes(&resources).into_iter().enumerate() {
+        for dependency in resource_dependencies {
+            dependencies[index] += 1;
+            dependents[dependency].push(index);
+        }
+    }
+
+    // the first declared resource among the ones ready is taken first
+    let mut ready: BTreeSet<usize> = (0..resources.len()).filter(|index| dependencies[*index] == 0).collect();
+    let mut order = Vec::with_capacity(resources.len());
+
+    while let Some(index) = ready.iter().next().cloned() {
+        ready.remove(&index);
+        order.push(index);
+
+        for dependent in &dependents[index] {
+            dependencies[*dependent] -= 1;
+
+            if dependencies[*dependent] == 0 {
+                ready.insert(*dependent);
+            }
+        }
+    }
+
+    if order.len() < resources.len() {
+        let cycle: Vec<String> = (0..resources.len())
+            .filter(|index| dependencies[*index] > 0)
+            .map(|index| format!("{} [{}]", resources[index].kind(), resources[index].name()))
+            .collect();
+
+        return Err(format!("Resources depend on each other: {}", cycle.join(", ")));
+    }
+
+    let mut resources: Vec<Option<Box<dyn Resource>>> = resources.into_iter().map(Some).collect();
+    let (absent, present): (Vec<usize>, Vec<usize>) = order.into_iter().partition(|index| resources[*index].as_ref().is_some_and(|resource| resource.is_absent()));
+
+    return Ok(present.into_iter().chain(absent.into_iter().rev())
+        .filter_map(|index| resources[index].take())
+        .collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dependency_order;
+    use report::ResourceKind;
+    use resource::{Change, Resource};
+    use sonar_api::SonarApiError;
+    use sonar_client::SonarClient;
+
+    struct TestResource {
+        kind: ResourceKind,
+        name: &'static str,
+        depends_on: Vec<(ResourceKind, &'static str)>,
+        absent: bool,
+    }
+
+    impl Resource for TestResource {
+        fn kind(&self) -> ResourceKind {
+            self.kind
+        }
+
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn depends_on(&self) -> Vec<(ResourceKind, String)> {
+            self.depends_on.iter().map(|(kind, name)| (*kind, name.to_string())).collect()
+        }
+
+        fn is_absent(&self) -> bool {
+            self.absent
+        }
+
+        fn diff(&self, _client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+            Ok(vec![])
+        }
+    }
+
+    fn resource(kind: ResourceKind, name: &'static str, depends_on: Vec<(ResourceKind, &'static str)>) -> Box<dyn Resource> {
+        Box::new(TestResource { kind, name, depends_on, absent: false })
+    }
+
+    fn absent(kind: ResourceKind, name: &'static str, depends_on: Vec<(ResourceKind, &'static str)>) -> Box<dyn Resource> {
+        Box::new(TestResource { kind, name, depends_on, absent: true })
+    }
+
+    fn names(resources: Vec<Box<dyn Resource>>) -> Vec<String> {
+        resources.iter().map(|resource| format!("{}/{}", resource.kind(), resource.name())).collect()
+    }
+
+    #[test]
+    fn applies_dependencies_first() {
+        let resources = vec![
+            resource(ResourceKind::Membership, "jdoe", vec![(ResourceKind::User, "jdoe"), (ResourceKind::Group, "developers")]),
+            resource(ResourceKind::User, "jdoe", vec![]),
+            resource(ResourceKind::Property, "sonar.core.serverBaseURL", vec![]),
+            resource(ResourceKind::Group, "developers", vec![]),
+        ];
+
+        assert_eq!(names(dependency_order(resources).unwrap()), vec!["user/jdoe", "property/sonar.core.serverBaseURL", "group/developers", "membership/jdoe"]);
+    }
+
+    #[test]
+    fn deletes_dependent_resources_first() {
+        let resources = vec![
+            absent(ResourceKind::Group, "testers", vec![]),
+            absent(ResourceKind::Membership, "testers", vec![(ResourceKind::Group, "testers")]),
+            resource(ResourceKind::Group, "developers", vec![]),
+        ];
+
+        assert_eq!(names(dependency_order(resources).unwrap()), vec!["group/developers", "membership/testers", "group/testers"]);
+    }
+
+    #[test]
+    fn rejects_dangling_references() {
+        let resources = vec![resource(ResourceKind::Membership, "jdoe", vec![(ResourceKind::Group, "developers")])];
+
+        let error = dependency_order(resources).err().unwrap();
+
+        assert_eq!(error, "membership [jdoe] references the group [developers] that is not declared");
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let resources = vec![
+            resource(ResourceKind::Group, "developers", vec![(ResourceKind::User, "jdoe")]),
+            resource(ResourceKind::User, "jdoe", vec![(ResourceKind::Group, "developers")]),
+            resource(ResourceKind::Property, "sonar.core.serverBaseURL", vec![]),
+        ];
+
+        let error = dependency_order(resources).err().unwrap();
+
+        assert_eq!(error, "Resources depend on each other: group [developers], user [jdoe]");
+    }
+}
@@ -1,6 +1,7 @@
 use structopt::StructOpt;
 
-use sonar_api::SonarApi;
+use sonar_as_code::secret_string::SecretString;
+use sonar_as_code::sonar_api::{SonarApi, SonarApiError};
 
 #[derive(StructOpt, Debug)]
 pub struct GenerateTokenCmd {
@@ -14,18 +15,18 @@ pub struct GenerateTokenCmd {
     username: String,
 
     #[structopt(name = "password", short = "p", default_value = "admin", about = "Password of the administrator")]
-    password: String,
+    password: SecretString,
 
     #[structopt(name = "nbAttempts", short = "a", default_value = "600", about = "Number of attemps to connect to the API (1sec between attempts)")]
     number_attempts: usize,
 }
 
-pub fn generate_token(cmd: GenerateTokenCmd) -> Result<(), reqwest::Error> {
-    let sonar_api = SonarApi::new(cmd.url.to_string(), cmd.username.to_string(), cmd.password.to_string(), cmd.number_attempts);
+pub fn generate_token(cmd: GenerateTokenCmd) -> Result<(), SonarApiError> {
+    let sonar_api = SonarApi::new(cmd.url.to_string(), cmd.username.to_string(), cmd.password.clone(), cmd.number_attempts);
 
-    sonar_api.wait_ready();
+    sonar_api.wait_ready()?;
 
-    println!("{}", sonar_api.generate_user_token(&cmd.username.to_string(), &cmd.name.to_string()));
+    println!("{}", sonar_api.generate_user_token(&cmd.username, &cmd.name)?.expose());
 
     Ok(())
 }
@@ -0,0 +1,180 @@
+use membership::MembershipDiff;
+use report::{Action, ResourceKind};
+use resource::{Change, Resource};
+use sonar_api::SonarApiError;
+use sonar_api_model::SonarGroupCreationRequest;
+use sonar_client::SonarClient;
+
+pub struct GroupResource {
+    group: SonarGroupCreationRequest,
+}
+
+impl GroupResource {
+    pub fn new(group: SonarGroupCreationRequest) -> GroupResource {
+        GroupResource { group }
+    }
+}
+
+impl Resource for GroupResource {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::Group
+    }
+
+    fn name(&self) -> String {
+        self.group.name.to_string()
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        match client.get_group_by_name(&self.group.name)? {
+            None => {
+                let group = self.group.clone();
+
+                return Ok(vec![Change::new(Action::Created, "does not exist".to_string(), move |client| client.create_group(&group))]);
+            }
+            Some(current_group) if current_group.description != self.group.description => {
+                let description = self.group.description.to_string();
+
+                return Ok(vec![Change::new(
+                    Action::Updated,
+                    format!("description is [{}], expecting [{}]", current_group.description, description),
+                    move |client| client.update_group_description(&current_group, &description),
+                )]);
+            }
+            Some(_) => {
+                return Ok(vec![]);
+            }
+        }
+    }
+}
+
+// Permissions granted to the group by the default permission template.
+pub struct GroupPermissions {
+    group: String,
+    permissions: Vec<String>,
+}
+
+impl GroupPermissions {
+    pub fn new(group: &str, permissions: &[String]) -> GroupPermissions {
+        GroupPermissions { group: group.to_string(), permissions: permissions.to_vec() }
+    }
+}
+
+impl Resource for GroupPermissions {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::Permission
+    }
+
+    fn name(&self) -> String {
+        self.group.to_string()
+    }
+
+    fn depends_on(&self) -> Vec<(ResourceKind, String)> {
+        return vec![(ResourceKind::Group, self.group.to_string())];
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let current_permissions: Vec<String> = client.get_template_groups()?.into_iter()
+            .filter(|template_group| template_group.name == self.group)
+            .flat_map(|template_group| template_group.permissions)
+            .collect();
+
+        let diff = MembershipDiff::between(&self.permissions, &current_permissions);
+        let mut changes = Vec::new();
+
+        for permission in diff.to_remove {
+            let group = self.group.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("permission [{}] is not expected", permission), move |client| client.remove_permission_to_group(&group, &permission)));
+        }
+
+        for permission in diff.to_add {
+            let group = self.group.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("permission [{}] is missing", permission), move |client| client.add_permission_to_group(&group, &permission)));
+        }
+
+        return Ok(changes);
+    }
+}
+
+// Members declared on the group, users whose memberships are managed on their side are not touched.
+pub struct GroupMembers {
+    group: String,
+    members: Vec<String>,
+    unmanaged: Vec<String>,
+}
+
+impl GroupMembers {
+    pub fn new(group: &str, members: &[String], unmanaged: &[&str]) -> GroupMembers {
+        GroupMembers { group: group.to_string(), members: members.to_vec(), unmanaged: unmanaged.iter().map(|login| login.to_string()).collect() }
+    }
+}
+
+impl Resource for GroupMembers {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::Membership
+    }
+
+    fn name(&self) -> String {
+        self.group.to_string()
+    }
+
+    fn depends_on(&self) -> Vec<(ResourceKind, String)> {
+        return vec![(ResourceKind::Group, self.group.to_string())];
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let unmanaged: Vec<&str> = self.unmanaged.iter().map(String::as_str).collect();
+        let diff = MembershipDiff::compute(&self.members, &client.get_group_members(&self.group)?, &unmanaged);
+        let mut changes = Vec::new();
+
+        for login in diff.to_remove {
+            let group = self.group.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("member [{}] is not expected", login), move |client| client.remove_user_from_group(&login, &group)));
+        }
+
+        for login in diff.to_add {
+            let group = self.group.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("member [{}] is missing", login), move |client| client.add_user_to_group(&login, &group)));
+        }
+
+        return Ok(changes);
+    }
+}
+
+// Group existing on SonarQube but not declared, it's deleted.
+pub struct AbsentGroup {
+    group: String,
+}
+
+impl AbsentGroup {
+    pub fn new(group: &str) -> AbsentGroup {
+        AbsentGroup { group: group.to_string() }
+    }
+}
+
+impl Resource for AbsentGroup {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::Group
+    }
+
+    fn name(&self) -> String {
+        self.group.to_string()
+    }
+
+    fn is_absent(&self) -> bool {
+        return true;
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        if client.get_group_by_name(&self.group)?.is_none() {
+            return Ok(vec![]);
+        }
+
+        let group = self.group.to_string();
+
+        return Ok(vec![Change::new(Action::Deleted, "is not declared".to_string(), move |client| client.delete_group(&group))]);
+    }
+}
@@ -0,0 +1,503 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, MutexGuard};
+
+use quality_profile::{deactivate_rule, profile_identity};
+use secret_string::{is_sensitive_property, SecretString};
+use sonar_api::{SonarApiError, ADMIN_GROUP, ADMIN_USER, DEFAULT_GROUP, TEMPLATE_PERMISSIONS};
+use sonar_api_model::{SonarGroup, SonarGroupCreationRequest, SonarNotification, SonarCustomRule, SonarProperty, SonarQualityProfile, SonarRule, SonarRuleParam, SonarTemplateGroup, SonarUser, SonarUserUpdate};
+use sonar_client::SonarClient;
+
+// SonarQube simulated in memory, it starts like a fresh instance. Every change is recorded, the reconciliation can be
+// tested and simulated without a server.
+pub struct InMemorySonar {
+    state: Mutex<InMemoryState>,
+}
+
+#[derive(Default)]
+struct InMemoryState {
+    properties: BTreeMap<String, String>,
+    // properties set with several values, their values are joined like SonarQube returns them
+    multi_valued_properties: BTreeSet<String>,
+    groups: BTreeMap<String, SonarGroup>,
+    // group and permission granted in the default template
+    template: BTreeSet<(String, String)>,
+    users: BTreeMap<String, SonarUser>,
+    // login and group
+    memberships: BTreeSet<(String, String)>,
+    notifications: BTreeSet<(String, SonarNotification)>,
+    // custom rules by key, deleted ones included
+    rules: BTreeMap<String, SonarRule>,
+    // backup of the quality profiles by language and name
+    quality_profiles: BTreeMap<(String, String), String>,
+    // language and name of the quality profiles provided by SonarQube, they cannot be restored
+    built_in_quality_profiles: BTreeSet<(String, String)>,
+    writes: Vec<String>,
+}
+
+impl Default for InMemorySonar {
+    fn default() -> Self {
+        InMemorySonar::new()
+    }
+}
+
+impl InMemorySonar {
+    pub fn new() -> InMemorySonar {
+        let mut state = InMemoryState::default();
+
+        state.groups.insert(DEFAULT_GROUP.to_string(), group(1, DEFAULT_GROUP, "Every authenticated user automatically belongs to this group"));
+        state.groups.insert(ADMIN_GROUP.to_string(), group(2, ADMIN_GROUP, "System administrators"));
+
+        for permission in &["admin", "issueadmin", "securityhotspotadmin"] {
+            state.template.insert((ADMIN_GROUP.to_string(), permission.to_string()));
+        }
+        for permission in &["codeviewer", "user"] {
+            state.template.insert((DEFAULT_GROUP.to_string(), permission.to_string()));
+        }
+
+        state.users.insert(ADMIN_USER.to_string(), SonarUser {
+            login: ADMIN_USER.to_string(),
+            name: "Administrator".to_string(),
+            password: None,
+            groups: vec![],
+            local: Some(true),
+            external_identity: Some(ADMIN_USER.to_string()),
+            external_provider: Some("sonarqube".to_string()),
+            password_policy: Default::default(),
+            active: Some(true),
+            anonymize: false,
+            email: None,
+            scm_accounts: Some(vec![]),
+            notifications: None,
+        });
+        state.memberships.insert((ADMIN_USER.to_string(), DEFAULT_GROUP.to_string()));
+        state.memberships.insert((ADMIN_USER.to_string(), ADMIN_GROUP.to_string()));
+
+        let sonar_way = ("java".to_string(), "Sonar way".to_string());
+        state.quality_profiles.insert(sonar_way.clone(), "<?xml version='1.0' encoding='UTF-8'?>\n<profile>\n  <name>Sonar way</name>\n  <language>java</language>\n  <rules/>\n</profile>\n".to_string());
+        state.built_in_quality_profiles.insert(sonar_way);
+
+        InMemorySonar { state: Mutex::new(state) }
+    }
+
+    // Sets a property with several values, it cannot be done through SonarClient.
+    pub fn set_multi_valued_property(&self, key: &str, values: &[&str]) {
+        let mut state = self.state();
+
+        state.properties.insert(key.to_string(), values.join(","));
+        state.multi_valued_properties.insert(key.to_string());
+        state.record(format!("set property {}", key));
+    }
+
+    // Changes done since the creation, in order.
+    pub fn writes(&self) -> Vec<String> {
+        self.state().writes.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, InMemoryState> {
+        self.state.lock().expect("The state of the in-memory SonarQube is not available.")
+    }
+}
+
+impl InMemoryState {
+    fn record(&mut self, write: String) {
+        self.writes.push(write);
+    }
+
+    fn check_group(&self, name: &str) -> Result<(), SonarApiError> {
+        if !self.groups.contains_key(name) {
+            return Err(SonarApiError::new(format!("Group [{}] does not exist", name)));
+        }
+
+        return Ok(());
+    }
+
+    fn with_groups(&self, user: &SonarUser) -> SonarUser {
+        let groups = self.memberships.iter().filter(|(member, _)| *member == user.login).map(|(_, group)| group.to_string()).collect();
+
+        return SonarUser { groups, ..user.clone() };
+    }
+
+    fn active_user(&mut self, login: &str) -> Result<&mut SonarUser, SonarApiError> {
+        match self.users.get_mut(login) {
+            Some(user) if user.is_active() => Ok(user),
+            _ => Err(SonarApiError::new(format!("User [{}] does not exist", login))),
+        }
+    }
+}
+
+// Rule as SonarQube returns it, the severity and the type of templates are MAJOR and CODE_SMELL.
+fn custom_rule(rule: &SonarCustomRule) -> SonarRule {
+    SonarRule {
+        key: rule.key(),
+        name: rule.name.to_string(),
+        severity: Some(rule.severity.as_deref().unwrap_or("MAJOR").to_string()),
+        rule_type: Some(rule.rule_type.as_deref().unwrap_or("CODE_SMELL").to_string()),
+        markdown_description: Some(rule.markdown_description.to_string()),
+        template_key: Some(rule.template_key.to_string()),
+        status: Some("READY".to_string()),
+        params: rule.params.iter().map(|(key, value)| SonarRuleParam { key: key.to_string(), default_value: Some(value.to_string()) }).collect(),
+    }
+}
+
+fn group(id: usize, name: &str, description: &str) -> SonarGroup {
+    SonarGroup { id, name: name.to_string(), description: description.to_string(), permissions: vec![] }
+}
+
+impl SonarClient for InMemorySonar {
+    fn get_property_value(&self, key: &str) -> Result<Option<String>, SonarApiError> {
+        // like SonarQube, secured values are never returned
+        if is_sensitive_property(key) {
+            return Ok(None);
+        }
+
+        return Ok(self.state().properties.get(key).cloned());
+    }
+
+    fn get_property_values(&self) -> Result<BTreeMap<String, String>, SonarApiError> {
+        return Ok(self.state().properties.iter()
+            .filter(|(key, _)| !is_sensitive_property(key))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect());
+    }
+
+    fn get_multi_valued_properties(&self) -> Result<BTreeSet<String>, SonarApiError> {
+        return Ok(self.state().multi_valued_properties.clone());
+    }
+
+    fn set_property(&self, property: &SonarProperty) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.properties.insert(property.name.to_string(), property.value.expose().to_string());
+        state.record(format!("set property {}", property.name));
+
+        return Ok(());
+    }
+
+    fn get_group_by_name(&self, name: &str) -> Result<Option<SonarGroup>, SonarApiError> {
+        return Ok(self.state().groups.get(name).cloned());
+    }
+
+    fn get_groups(&self) -> Result<Vec<SonarGroup>, SonarApiError> {
+        return Ok(self.state().groups.values().cloned().collect());
+    }
+
+    fn create_group(&self, group_request: &SonarGroupCreationRequest) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        if state.groups.contains_key(&group_request.name) {
+            return Err(SonarApiError::new(format!("Group [{}] already exists", group_request.name)));
+        }
+
+        let id = state.groups.values().map(|group| group.id).max().unwrap_or_default() + 1;
+        state.groups.insert(group_request.name.to_string(), group(id, &group_request.name, &group_request.description));
+        state.record(format!("create group {}", group_request.name));
+
+        return Ok(());
+    }
+
+    fn update_group_description(&self, current_group: &SonarGroup, description: &str) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        match state.groups.get_mut(&current_group.name) {
+            Some(group) => group.description = description.to_string(),
+            None => return Err(SonarApiError::new(format!("Group [{}] does not exist", current_group.name))),
+        }
+        state.record(format!("update group {}", current_group.name));
+
+        return Ok(());
+    }
+
+    fn delete_group(&self, name: &str) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        if name == DEFAULT_GROUP || name == ADMIN_GROUP {
+            return Err(SonarApiError::new(format!("Default group [{}] cannot be deleted", name)));
+        }
+
+        if state.groups.remove(name).is_none() {
+            return Err(SonarApiError::new(format!("Group [{}] does not exist", name)));
+        }
+
+        state.template.retain(|(group, _)| group != name);
+        state.memberships.retain(|(_, group)| group != name);
+        state.record(format!("delete group {}", name));
+
+        return Ok(());
+    }
+
+    fn get_template_groups(&self) -> Result<Vec<SonarTemplateGroup>, SonarApiError> {
+        let state = self.state();
+
+        let mut template_groups: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+        for (group, permission) in &state.template {
+            template_groups.entry(group).or_default().push(permission.to_string());
+        }
+
+        return Ok(template_groups.into_iter().map(|(name, permissions)| SonarTemplateGroup { name: name.to_string(), permissions }).collect());
+    }
+
+    fn add_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.check_group(group)?;
+        if !TEMPLATE_PERMISSIONS.contains(&permission) {
+            return Err(SonarApiError::new(format!("Permission [{}] does not exist", permission)));
+        }
+
+        state.template.insert((group.to_string(), permission.to_string()));
+        state.record(format!("add permission {} to {}", permission, group));
+
+        return Ok(());
+    }
+
+    fn remove_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.check_group(group)?;
+
+        state.template.remove(&(group.to_string(), permission.to_string()));
+        state.record(format!("remove permission {} from {}", permission, group));
+
+        return Ok(());
+    }
+
+    fn get_user(&self, login: &str) -> Result<Option<SonarUser>, SonarApiError> {
+        let state = self.state();
+
+        return Ok(state.users.get(login).map(|user| state.with_groups(user)));
+    }
+
+    fn get_users(&self) -> Result<Vec<SonarUser>, SonarApiError> {
+        let state = self.state();
+
+        return Ok(state.users.values().map(|user| state.with_groups(user)).collect());
+    }
+
+    fn create_user(&self, user: &SonarUser) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        if state.users.get(&user.login).is_some_and(|current_user| current_user.is_active()) {
+            return Err(SonarApiError::new(format!("An active user with login [{}] already exists", user.login)));
+        }
+
+        if user.is_local() && user.password.is_none() {
+            return Err(SonarApiError::new("Password is mandatory".to_string()));
+        }
+
+        let created_user = SonarUser {
+            password: None,
+            groups: vec![],
+            local: Some(user.is_local()),
+            external_identity: Some(user.login.to_string()),
+            external_provider: Some("sonarqube".to_string()),
+            active: Some(true),
+            notifications: None,
+            ..user.clone()
+        };
+
+        state.users.insert(user.login.to_string(), created_user);
+        state.memberships.insert((user.login.to_string(), DEFAULT_GROUP.to_string()));
+        state.record(format!("create user {}", user.login));
+
+        return Ok(());
+    }
+
+    fn update_user(&self, update: &SonarUserUpdate) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        let user = state.active_user(&update.login)?;
+        if let Some(name) = &update.name {
+            user.name = name.to_string();
+        }
+        if let Some(email) = &update.email {
+            user.email = Some(email.to_string());
+        }
+        if let Some(scm_accounts) = &update.scm_accounts {
+            user.scm_accounts = Some(scm_accounts.clone());
+        }
+
+        state.record(format!("update user {}", update.login));
+
+        return Ok(());
+    }
+
+    fn update_identity_provider(&self, user: &SonarUser) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        let current_user = state.active_user(&user.login)?;
+        current_user.local = Some(false);
+        current_user.external_provider = user.external_provider.clone();
+        current_user.external_identity = Some(user.external_identity.clone().unwrap_or_else(|| user.login.to_string()));
+
+        state.record(format!("update identity provider of {}", user.login));
+
+        return Ok(());
+    }
+
+    fn change_user_password(&self, login: &str, _password: &SecretString) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.active_user(login)?;
+        state.record(format!("change password of {}", login));
+
+        return Ok(());
+    }
+
+    fn deactivate_user(&self, login: &str, anonymize: bool) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        let user = state.active_user(login)?;
+        user.active = Some(false);
+
+        // personal data is removed, the login is kept to find the user again
+        if anonymize {
+            user.name = login.to_string();
+            user.email = None;
+            user.scm_accounts = None;
+        }
+
+        state.memberships.retain(|(member, _)| member != login);
+        state.notifications.retain(|(subscriber, _)| subscriber != login);
+        state.record(format!("deactivate user {}{}", login, if anonymize { " (anonymized)" } else { "" }));
+
+        return Ok(());
+    }
+
+    fn get_user_groups(&self, login: &str) -> Result<Vec<String>, SonarApiError> {
+        return Ok(self.state().memberships.iter().filter(|(member, _)| member == login).map(|(_, group)| group.to_string()).collect());
+    }
+
+    fn get_group_members(&self, group: &str) -> Result<Vec<String>, SonarApiError> {
+        return Ok(self.state().memberships.iter().filter(|(_, name)| name == group).map(|(member, _)| member.to_string()).collect());
+    }
+
+    fn add_user_to_group(&self, login: &str, group: &str) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.active_user(login)?;
+        state.check_group(group)?;
+
+        state.memberships.insert((login.to_string(), group.to_string()));
+        state.record(format!("add {} to {}", login, group));
+
+        return Ok(());
+    }
+
+    fn remove_user_from_group(&self, login: &str, group: &str) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.active_user(login)?;
+        state.check_group(group)?;
+
+        state.memberships.remove(&(login.to_string(), group.to_string()));
+        state.record(format!("remove {} from {}", login, group));
+
+        return Ok(());
+    }
+
+    fn get_user_notifications(&self, login: &str) -> Result<Vec<SonarNotification>, SonarApiError> {
+        return Ok(self.state().notifications.iter().filter(|(subscriber, _)| subscriber == login).map(|(_, notification)| notification.clone()).collect());
+    }
+
+    fn add_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.active_user(login)?;
+
+        state.notifications.insert((login.to_string(), notification.clone()));
+        state.record(format!("add notification {} to {}", notification, login));
+
+        return Ok(());
+    }
+
+    fn remove_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        state.notifications.remove(&(login.to_string(), notification.clone()));
+        state.record(format!("remove notification {} from {}", notification, login));
+
+        return Ok(());
+    }
+
+    fn get_rule(&self, key: &str) -> Result<Option<SonarRule>, SonarApiError> {
+        return Ok(self.state().rules.get(key).cloned());
+    }
+
+    fn get_custom_rules(&self) -> Result<Vec<SonarRule>, SonarApiError> {
+        return Ok(self.state().rules.values().filter(|rule| !rule.is_removed()).cloned().collect());
+    }
+
+    fn create_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        if state.rules.get(&rule.key()).is_some_and(|current| !current.is_removed()) {
+            return Err(SonarApiError::new(format!("Rule [{}] already exists", rule.key())));
+        }
+
+        state.rules.insert(rule.key(), custom_rule(rule));
+        state.record(format!("create rule {}", rule.key()));
+
+        return Ok(());
+    }
+
+    fn update_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        let current = match state.rules.get_mut(&rule.key()) {
+            Some(current) if !current.is_removed() => current,
+            _ => return Err(SonarApiError::new(format!("Rule [{}] does not exist", rule.key()))),
+        };
+
+        let mut updated = custom_rule(rule);
+        updated.rule_type = current.rule_type.take();
+        updated.severity = rule.severity.as_ref().or(current.severity.as_ref()).cloned();
+        *current = updated;
+        state.record(format!("update rule {}", rule.key()));
+
+        return Ok(());
+    }
+
+    fn delete_rule(&self, key: &str) -> Result<(), SonarApiError> {
+        let mut state = self.state();
+
+        match state.rules.get_mut(key) {
+            Some(rule) if !rule.is_removed() => rule.status = Some("REMOVED".to_string()),
+            _ => return Err(SonarApiError::new(format!("Rule [{}] does not exist", key))),
+        }
+        // the rule is deactivated in every quality profile
+        for backup in state.quality_profiles.values_mut() {
+            *backup = deactivate_rule(backup, key);
+        }
+        state.record(format!("delete rule {}", key));
+
+        return Ok(());
+    }
+
+    fn get_quality_profiles(&self) -> Result<Vec<SonarQualityProfile>, SonarApiError> {
+        let state = self.state();
+
+        return Ok(state.quality_profiles.keys()
+            .map(|profile| (profile, state.built_in_quality_profiles.contains(profile)))
+            .map(|((language, name), built_in)| SonarQualityProfile { key: format!("{}-{}", language, name), name: name.to_string(), language: language.to_string(), is_built_in: built_in, is_default: built_in })
+            .collect());
+    }
+
+    fn backup_quality_profile(&self, language: &str, name: &str) -> Result<String, SonarApiError> {
+        return self.state().quality_profiles.get(&(language.to_string(), name.to_string())).cloned()
+            .ok_or_else(|| SonarApiError::new(format!("Quality profile [{}] of [{}] does not exist", name, language)));
+    }
+
+    fn restore_quality_profile(&self, backup: &str) -> Result<(), SonarApiError> {
+        let (name, language) = profile_identity(backup).map_err(SonarApiError::new)?;
+        let mut state = self.state();
+
+        if state.built_in_quality_profiles.contains(&(language.to_string(), name.to_string())) {
+            return Err(SonarApiError::new(format!("Built-in quality profile [{}] of [{}] cannot be restored", name, language)));
+        }
+
+        state.record(format!("restore quality profile {} of {}", name, language));
+        state.quality_profiles.insert((language, name), backup.to_string());
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,40 @@
+// Typed client of the SonarQube web API and reconciler applying a configuration file to SonarQube.
+
+#![allow(clippy::needless_return)]
+
+#[macro_use]
+extern crate log;
+extern crate reqwest;
+extern crate retry;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+#[cfg(test)]
+extern crate tiny_http;
+extern crate toml;
+
+pub mod config_file_loader;
+pub mod config_file_model;
+pub mod config_file_overlay;
+pub mod custom_rule_resource;
+pub mod dependency_graph;
+pub mod group_resource;
+pub mod in_memory_sonar;
+pub mod membership;
+pub mod property_resource;
+pub mod quality_profile;
+pub mod quality_profile_resource;
+pub mod rate_limiter;
+pub mod reconcile;
+pub mod report;
+pub mod resource;
+pub mod secret_string;
+pub mod server_backup;
+pub mod sonar_api;
+pub mod sonar_api_model;
+pub mod sonar_client;
+pub mod sonar_snapshot;
+pub mod user_resource;
+pub mod variable_resolver;
@@ -1,26 +1,33 @@
+#![allow(clippy::needless_return)]
+
 extern crate clap;
 extern crate clap_verbosity_flag;
+extern crate env_logger;
 #[macro_use]
 extern crate log;
-extern crate reqwest;
-extern crate retry;
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde_yaml;
+extern crate sonar_as_code;
 extern crate structopt;
 
-use std::io::Error;
+use std::process;
 
 use clap_verbosity_flag::Verbosity;
+use log::LevelFilter;
 use structopt::StructOpt;
 
+mod configuration_options;
+mod report_options;
+
 mod setup;
+mod validate;
+mod check;
+mod plan;
 mod generate_token;
-mod config_file_model;
-mod sonar_api_model;
-mod sonar_api;
+mod backup;
+mod restore;
 
+const SUCCESS_EXIT_CODE: i32 = 0;
+const ERROR_EXIT_CODE: i32 = 1;
+const DRIFT_EXIT_CODE: i32 = 2;
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Setup Sonarqube from a configuration file")]
@@ -41,25 +48,82 @@ pub enum SubCmd {
         setup: setup::SetupCmd,
     },
 
+    #[structopt(name = "validate", about = "Validate a file without contacting SonarQube")]
+    ValidateCmd {
+        #[structopt(flatten)]
+        validate: validate::ValidateCmd,
+    },
+
+    #[structopt(name = "check", about = "Check whether SonarQube drifted from a file (exit code 2 on drift)")]
+    CheckCmd {
+        #[structopt(flatten)]
+        check: check::CheckCmd,
+    },
+
+    #[structopt(name = "plan", about = "Simulate the setup on a fresh SonarQube without contacting it")]
+    PlanCmd {
+        #[structopt(flatten)]
+        plan: plan::PlanCmd,
+    },
+
     #[structopt(name = "generate-token", about = "Generate a user token")]
     GenerateTokenCmd {
         #[structopt(flatten)]
         generate_token: generate_token::GenerateTokenCmd,
     },
+
+    #[structopt(name = "backup", about = "Back up the configuration and the quality profiles of SonarQube in a timestamped directory")]
+    BackupCmd {
+        #[structopt(flatten)]
+        backup: backup::BackupCmd,
+    },
+
+    #[structopt(name = "restore", about = "Restore a backup on SonarQube")]
+    RestoreCmd {
+        #[structopt(flatten)]
+        restore: restore::RestoreCmd,
+    },
 }
 
-fn main() -> Result<(), Error> {
+fn main() {
     let cmd: MainCmd = MainCmd::from_args();
 
-    match cmd.cmd {
+    env_logger::Builder::new()
+        .filter_level(cmd.verbose.log_level().map(|level| level.to_level_filter()).unwrap_or(LevelFilter::Off))
+        .init();
+
+    let result = match cmd.cmd {
         SubCmd::SetupCmd { setup: setup_cmd } => {
-            setup::setup(setup_cmd);
+            setup::setup(setup_cmd).map(|_| SUCCESS_EXIT_CODE).map_err(|error| error.to_string())
+        }
+        SubCmd::ValidateCmd { validate: validate_cmd } => {
+            validate::validate(validate_cmd).map(|_| SUCCESS_EXIT_CODE)
+        }
+        SubCmd::CheckCmd { check: check_cmd } => {
+            check::check(check_cmd).map(|in_sync| if in_sync { SUCCESS_EXIT_CODE } else { DRIFT_EXIT_CODE }).map_err(|error| error.to_string())
+        }
+        SubCmd::PlanCmd { plan: plan_cmd } => {
+            plan::plan(plan_cmd).map(|_| SUCCESS_EXIT_CODE).map_err(|error| error.to_string())
         }
         SubCmd::GenerateTokenCmd {generate_token: generate_token_cmd } => {
-            generate_token::generate_token(generate_token_cmd);
+            generate_token::generate_token(generate_token_cmd).map(|_| SUCCESS_EXIT_CODE).map_err(|error| error.to_string())
         }
-    }
+        SubCmd::BackupCmd { backup: backup_cmd } => {
+            backup::backup(backup_cmd).map(|_| SUCCESS_EXIT_CODE).map_err(|error| error.to_string())
+        }
+        SubCmd::RestoreCmd { restore: restore_cmd } => {
+            restore::restore(restore_cmd).map(|_| SUCCESS_EXIT_CODE).map_err(|error| error.to_string())
+        }
+    };
 
-    debug!("Finish gracefully :-)");
-    Ok(())
+    match result {
+        Ok(exit_code) => {
+            debug!("Finish gracefully :-)");
+            process::exit(exit_code);
+        }
+        Err(error) => {
+            eprintln!("{}.", error);
+            process::exit(ERROR_EXIT_CODE);
+        }
+    }
 }
@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+
+use sonar_api_model::{SonarGroupCreationRequest, SonarUser};
+
+// Memberships to add and to remove to converge from the current memberships (e.g. groups of a user, notification
+// subscriptions) to the desired ones. Entries are sorted, the reconciliation order is always the same.
+#[derive(Debug, PartialEq)]
+pub struct MembershipDiff<T = String> {
+    pub to_add: Vec<T>,
+    pub to_remove: Vec<T>,
+    pub unchanged: Vec<T>,
+}
+
+impl<T: Ord + Clone> MembershipDiff<T> {
+    pub fn between(desired: &[T], current: &[T]) -> MembershipDiff<T> {
+        let desired: BTreeSet<&T> = desired.iter().collect();
+        let current: BTreeSet<&T> = current.iter().collect();
+
+        MembershipDiff {
+            to_add: desired.difference(&current).map(|&entry| entry.clone()).collect(),
+            to_remove: current.difference(&desired).map(|&entry| entry.clone()).collect(),
+            unchanged: desired.intersection(&current).map(|&entry| entry.clone()).collect(),
+        }
+    }
+}
+
+impl MembershipDiff<String> {
+    // Unmanaged entries (like the default group every user belongs to) are never added nor removed.
+    pub fn compute(desired: &[String], current: &[String], unmanaged: &[&str]) -> MembershipDiff {
+        let managed = |entries: &[String]| -> Vec<String> {
+            entries.iter().filter(|entry| !unmanaged.contains(&entry.as_str())).cloned().collect()
+        };
+
+        MembershipDiff::between(&managed(desired), &managed(current))
+    }
+}
+
+// Groups a user must be member of: the groups listed for the user and the groups listing the user as member. Both sides
+// are merged, a membership declared on one side is never removed because of the other side.
+pub fn desired_user_groups(user: &SonarUser, groups: &[SonarGroupCreationRequest]) -> Vec<String> {
+    let mut desired = user.groups.clone();
+
+    for group in groups {
+        if group.members.as_ref().is_some_and(|members| members.contains(&user.login)) {
+            desired.push(group.name.to_string());
+        }
+    }
+
+    return desired;
+}
+
+// Logins whose memberships are not reconciled from the group side: users declared in the configuration (their
+// memberships are reconciled from the user side) and the administrator.
+pub fn unmanaged_group_members<'a>(users: &'a [SonarUser], admin: &'a str) -> Vec<&'a str> {
+    let mut unmanaged: Vec<&str> = users.iter().map(|user| user.login.as_str()).collect();
+    unmanaged.push(admin);
+
+    return unmanaged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{desired_user_groups, unmanaged_group_members, MembershipDiff};
+    use sonar_api_model::{SonarGroupCreationRequest, SonarUser};
+
+    fn entries(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn adds_missing_entries() {
+        let diff = MembershipDiff::compute(&entries(&["analysers", "developers"]), &entries(&["developers"]), &[]);
+
+        assert_eq!(diff.to_add, entries(&["analysers"]));
+        assert!(diff.to_remove.is_empty());
+        assert_eq!(diff.unchanged, entries(&["developers"]));
+    }
+
+    #[test]
+    fn removes_entries_not_desired() {
+        let diff = MembershipDiff::compute(&entries(&["developers"]), &entries(&["developers", "analysers"]), &[]);
+
+        assert!(diff.to_add.is_empty());
+        assert_eq!(diff.to_remove, entries(&["analysers"]));
+    }
+
+    #[test]
+    fn keeps_desired_entries_already_present() {
+        let diff = MembershipDiff::compute(&entries(&["analysers", "developers"]), &entries(&["developers", "analysers"]), &[]);
+
+        assert!(diff.to_add.is_empty());
+        assert!(diff.to_remove.is_empty());
+        assert_eq!(diff.unchanged, entries(&["analysers", "developers"]));
+    }
+
+    #[test]
+    fn never_touches_unmanaged_entries() {
+        let diff = MembershipDiff::compute(&entries(&["analysers"]), &entries(&["sonar-users"]), &["sonar-users"]);
+        assert_eq!(diff.to_add, entries(&["analysers"]));
+        assert!(diff.to_remove.is_empty());
+
+        let diff = MembershipDiff::compute(&entries(&["sonar-users"]), &entries(&[]), &["sonar-users"]);
+        assert_eq!(diff, MembershipDiff { to_add: vec![], to_remove: vec![], unchanged: vec![] });
+    }
+
+    #[test]
+    fn removes_everything_when_nothing_is_desired() {
+        let diff = MembershipDiff::compute(&entries(&[]), &entries(&["developers", "analysers", "sonar-users"]), &["sonar-users"]);
+
+        assert!(diff.to_add.is_empty());
+        assert_eq!(diff.to_remove, entries(&["analysers", "developers"]));
+    }
+
+    #[test]
+    fn ignores_duplicated_entries() {
+        let diff = MembershipDiff::compute(&entries(&["analysers", "analysers"]), &entries(&[]), &[]);
+
+        assert_eq!(diff.to_add, entries(&["analysers"]));
+    }
+
+    #[test]
+    fn computes_diff_of_any_entry() {
+        let diff = MembershipDiff::between(&[("NewIssues", Some("project")), ("NewAlerts", None)], &[("NewAlerts", None), ("ChangesOnMyIssue", None)]);
+
+        assert_eq!(diff.to_add, vec![("NewIssues", Some("project"))]);
+        assert_eq!(diff.to_remove, vec![("ChangesOnMyIssue", None)]);
+        assert_eq!(diff.unchanged, vec![("NewAlerts", None)]);
+    }
+
+    fn user(login: &str, groups: &[&str]) -> SonarUser {
+        SonarUser {
+            login: login.to_string(),
+            name: login.to_string(),
+            password: None,
+            groups: entries(groups),
+            local: None,
+            external_identity: None,
+            external_provider: None,
+            password_policy: Default::default(),
+            active: None,
+            anonymize: false,
+            email: None,
+            scm_accounts: None,
+            notifications: None,
+        }
+    }
+
+    fn group(name: &str, members: Option<&[&str]>) -> SonarGroupCreationRequest {
+        SonarGroupCreationRequest { name: name.to_string(), description: String::new(), permissions: vec![], members: members.map(entries) }
+    }
+
+    #[test]
+    fn merges_groups_declaring_the_user_as_member() {
+        let groups = vec![group("analysers", Some(&["jdoe"])), group("developers", None), group("testers", Some(&["other"]))];
+
+        let desired = desired_user_groups(&user("jdoe", &["developers"]), &groups);
+
+        assert_eq!(desired, entries(&["developers", "analysers"]));
+    }
+
+    #[test]
+    fn group_side_never_manages_declared_users_nor_admin() {
+        let users = vec![user("jdoe", &[])];
+        let unmanaged = unmanaged_group_members(&users, "admin");
+
+        let diff = MembershipDiff::compute(&entries(&["ldap-user", "jdoe"]), &entries(&["admin", "jdoe", "former"]), &unmanaged);
+
+        assert_eq!(diff.to_add, entries(&["ldap-user"]));
+        assert_eq!(diff.to_remove, entries(&["former"]));
+    }
+}
@@ -0,0 +1,43 @@
+use structopt::StructOpt;
+
+use configuration_options::ConfigurationOptions;
+use report_options::ReportOptions;
+use sonar_as_code::config_file_model::ConfigurationFile;
+use sonar_as_code::in_memory_sonar::InMemorySonar;
+use sonar_as_code::reconcile::{reconcile, ReconcileOptions};
+use sonar_as_code::report::Report;
+use sonar_as_code::sonar_api::SonarApiError;
+
+#[derive(StructOpt, Debug)]
+pub struct PlanCmd {
+    #[structopt(flatten)]
+    configuration: ConfigurationOptions,
+
+    #[structopt(flatten)]
+    report: ReportOptions,
+}
+
+// Simulates the setup on a fresh SonarQube without contacting any server, the changes that would be done are printed.
+pub fn plan(cmd: PlanCmd) -> Result<(), SonarApiError> {
+    let config_file: ConfigurationFile = cmd.configuration.load().map_err(SonarApiError::new)?;
+    let options = ReconcileOptions { keep_going: true, env_file: cmd.configuration.env_file.clone(), ..ReconcileOptions::default() };
+
+    let sonar = InMemorySonar::new();
+
+    let mut report = Report::new("plan");
+    let result = reconcile(config_file, &sonar, &options, &mut report);
+
+    cmd.report.publish(&report).map_err(SonarApiError::new)?;
+
+    let writes = sonar.writes();
+    println!("{} change(s) on a fresh SonarQube:", writes.len());
+    for write in &writes {
+        println!("  {}", write);
+    }
+
+    for failure in report.failures() {
+        eprintln!("Failed {}/{}: {}.", failure.kind, failure.name, failure.error.as_deref().unwrap_or_default());
+    }
+
+    return result;
+}
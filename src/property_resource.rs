@@ -0,0 +1,46 @@
+use report::{Action, ResourceKind};
+use resource::{Change, Resource};
+use sonar_api::SonarApiError;
+use sonar_api_model::SonarProperty;
+use sonar_client::SonarClient;
+
+pub struct PropertyResource {
+    property: SonarProperty,
+}
+
+impl PropertyResource {
+    pub fn new(property: SonarProperty) -> PropertyResource {
+        PropertyResource { property }
+    }
+}
+
+impl Resource for PropertyResource {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::Property
+    }
+
+    fn name(&self) -> String {
+        self.property.name.to_string()
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let property = self.property.clone();
+        let set_property = move |client: &dyn SonarClient| client.set_property(&property);
+
+        let current_value = client.get_property_value(&self.property.name)?;
+        if current_value.as_deref() == Some(self.property.value.expose()) {
+            return Ok(vec![]);
+        }
+
+        // values of secured properties are never returned by SonarQube, they are always set
+        if self.property.value.is_secret() {
+            return Ok(vec![Change::blind(Action::Updated, "secret value is set".to_string(), set_property)]);
+        }
+
+        return Ok(vec![Change::new(
+            if current_value.is_some() { Action::Updated } else { Action::Created },
+            format!("value is {}, expecting [{}]", current_value.map(|value| format!("[{}]", value)).unwrap_or_else(|| "not set".to_string()), self.property.value),
+            set_property,
+        )]);
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use config_file_model::QualityProfile;
+
+// Quality profile declared by the configuration, restored from its XML backup.
+#[derive(Debug, Clone)]
+pub struct QualityProfileBackup {
+    pub file: String,
+    pub name: String,
+    pub language: String,
+    pub backup: String,
+}
+
+impl QualityProfileBackup {
+    pub fn read(file: &str) -> Result<QualityProfileBackup, String> {
+        let backup = fs::read_to_string(file).map_err(|error| format!("Cannot read the quality profile [{}]: {}", file, error))?;
+        let (name, language) = profile_identity(&backup).map_err(|error| format!("{} in [{}]", error, file))?;
+
+        return Ok(QualityProfileBackup { file: file.to_string(), name, language, backup });
+    }
+
+    // Backups are read upfront, a missing file does not leave SonarQube half-configured.
+    pub fn from_configuration_quality_profiles(profiles: &[QualityProfile], resolver: &dyn Fn(&String) -> String) -> Result<Vec<QualityProfileBackup>, String> {
+        return profiles.iter().map(|profile| QualityProfileBackup::read(&resolver(&profile.file))).collect();
+    }
+
+    // Keys of the rules activated by the profile, e.g. java:S124.
+    pub fn rule_keys(&self) -> Vec<String> {
+        return active_rules(&self.backup).into_keys().collect();
+    }
+
+    // Returns whether the backup of the current profile has the same name, language and rules. The priority and the
+    // parameters of a rule are only compared when the backup specifies them: SonarQube adds their default values.
+    pub fn is_same_as(&self, current_backup: &str) -> bool {
+        if profile_identity(current_backup).ok() != Some((self.name.to_string(), self.language.to_string())) {
+            return false;
+        }
+
+        let rules = active_rules(&self.backup);
+        let current_rules = active_rules(current_backup);
+
+        return rules.len() == current_rules.len()
+            && rules.iter().all(|(key, rule)| current_rules.get(key).is_some_and(|current_rule| rule.matches(current_rule)));
+    }
+}
+
+// Rule activated by a quality profile.
+#[derive(Debug)]
+struct ActiveRule {
+    priority: Option<String>,
+    params: BTreeMap<String, String>,
+}
+
+impl ActiveRule {
+    fn matches(&self, current: &ActiveRule) -> bool {
+        return self.priority.as_ref().is_none_or(|priority| current.priority.as_ref() == Some(priority))
+            && self.params.iter().all(|(key, value)| current.params.get(key) == Some(value));
+    }
+}
+
+// Rules activated in the backup by key (e.g. java:S124), whatever their order.
+fn active_rules(backup: &str) -> BTreeMap<String, ActiveRule> {
+    let mut rules = BTreeMap::new();
+
+    for rule in backup.split("<rule>").skip(1) {
+        let rule = rule.split("</rule>").next().unwrap_or_default();
+
+        // parameters have a key too, they are read apart from the rule
+        let (rule, parameters) = match (rule.find("<parameters>"), rule.find("</parameters>")) {
+            (Some(start), Some(end)) if start < end => (format!("{}{}", &rule[..start], &rule[end..]), &rule[start..end]),
+            _ => (rule.to_string(), ""),
+        };
+
+        let key = match (element_text(&rule, "repositoryKey"), element_text(&rule, "key")) {
+            (Some(repository), Some(key)) => format!("{}:{}", repository, key),
+            _ => continue,
+        };
+
+        let params = parameters.split("<parameter>").skip(1)
+            .filter_map(|parameter| Some((element_text(parameter, "key")?, element_text(parameter, "value").unwrap_or_default())))
+            .collect();
+
+        rules.insert(key, ActiveRule { priority: element_text(&rule, "priority"), params });
+    }
+
+    return rules;
+}
+
+// Name and language of the quality profile backed up in the XML document (see /api/qualityprofiles/backup), they are
+// specified before its rules.
+pub fn profile_identity(backup: &str) -> Result<(String, String), String> {
+    let name = element_text(backup, "name").ok_or_else(|| "The quality profile backup has no [name]".to_string())?;
+    let language = element_text(backup, "language").ok_or_else(|| "The quality profile backup has no [language]".to_string())?;
+
+    return Ok((name, language));
+}
+
+// Backup without the activation of the rule, like SonarQube does when the rule is deleted.
+pub fn deactivate_rule(backup: &str, key: &str) -> String {
+    let mut deactivated = String::new();
+    let mut remaining = backup;
+
+    while let Some(start) = remaining.find("<rule>") {
+        let end = remaining[start..].find("</rule>").map(|end| start + end + "</rule>".len()).unwrap_or(remaining.len());
+        let rule = &remaining[start..end];
+
+        deactivated.push_str(&remaining[..start]);
+        if !active_rules(rule).contains_key(key) {
+            deactivated.push_str(rule);
+        }
+        remaining = &remaining[end..];
+    }
+    deactivated.push_str(remaining);
+
+    return deactivated;
+}
+
+// Text of the first element with this tag.
+fn element_text(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+
+    return Some(unescape_xml(xml[start..end].trim()));
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deactivate_rule, profile_identity, QualityProfileBackup};
+
+    #[test]
+    fn reads_name_and_language() {
+        let backup = r#"<?xml version='1.0' encoding='UTF-8'?>
+<profile>
+  <name>Strict &amp; safe</name>
+  <language>java</language>
+  <rules>
+    <rule>
+      <repositoryKey>java</repositoryKey>
+      <key>S124</key>
+      <name>Track comments</name>
+    </rule>
+  </rules>
+</profile>"#;
+
+        assert_eq!(profile_identity(backup).unwrap(), ("Strict & safe".to_string(), "java".to_string()));
+    }
+
+    #[test]
+    fn rejects_backup_without_language() {
+        assert_eq!(profile_identity("<profile><name>Strict</name></profile>").unwrap_err(), "The quality profile backup has no [language]");
+    }
+
+    #[test]
+    fn lists_activated_rules() {
+        let profile = QualityProfileBackup {
+            file: "strict.xml".to_string(),
+            name: "Strict".to_string(),
+            language: "java".to_string(),
+            backup: "<profile><name>Strict</name><language>java</language><rules>\
+                <rule><repositoryKey>java</repositoryKey><key>S124</key><priority>MAJOR</priority></rule>\
+                <rule><repositoryKey>java</repositoryKey><key>no-todo</key><templateKey>S124</templateKey></rule>\
+                </rules></profile>".to_string(),
+        };
+
+        assert_eq!(profile.rule_keys(), vec!["java:S124", "java:no-todo"]);
+    }
+
+    #[test]
+    fn ignores_declaration_and_indentation() {
+        let profile = QualityProfileBackup {
+            file: "strict.xml".to_string(),
+            name: "Strict".to_string(),
+            language: "java".to_string(),
+            backup: "<?xml version='1.0' encoding='UTF-8'?>\n<profile>\n  <name>Strict</name>\n  <language>java</language>\n</profile>\n".to_string(),
+        };
+
+        assert!(profile.is_same_as("<?xml version=\"1.0\" encoding=\"UTF-8\"?><profile><name>Strict</name>\n    <language>java</language></profile>"));
+        assert!(!profile.is_same_as("<profile><name>Strict</name><language>js</language></profile>"));
+    }
+
+    const DECLARED: &str = "<?xml version='1.0' encoding='UTF-8'?>
+<profile>
+  <name>Strict</name>
+  <language>java</language>
+  <rules>
+    <rule>
+      <repositoryKey>java</repositoryKey>
+      <key>S107</key>
+      <priority>CRITICAL</priority>
+      <parameters>
+        <parameter>
+          <key>max</key>
+          <value>5</value>
+        </parameter>
+      </parameters>
+    </rule>
+    <rule>
+      <repositoryKey>java</repositoryKey>
+      <key>S124</key>
+    </rule>
+  </rules>
+</profile>";
+
+    fn declared() -> QualityProfileBackup {
+        QualityProfileBackup { file: "strict.xml".to_string(), name: "Strict".to_string(), language: "java".to_string(), backup: DECLARED.to_string() }
+    }
+
+    // Backup returned by SonarQube: rules in another order, with their type, priority and default parameters.
+    const CURRENT: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><profile><name>Strict</name><language>java</language><rules>\
+        <rule><repositoryKey>java</repositoryKey><key>S124</key><type>CODE_SMELL</type><priority>MINOR</priority>\
+        <parameters><parameter><key>regularExpression</key><value/></parameter><parameter><key>message</key><value>TODO</value></parameter></parameters></rule>\
+        <rule><repositoryKey>java</repositoryKey><key>S107</key><type>CODE_SMELL</type><priority>CRITICAL</priority>\
+        <parameters><parameter><key>max</key><value>5</value></parameter><parameter><key>constructorMax</key><value>7</value></parameter></parameters></rule>\
+        </rules></profile>";
+
+    #[test]
+    fn compares_rules_whatever_their_order_and_default_values() {
+        assert!(declared().is_same_as(CURRENT));
+    }
+
+    #[test]
+    fn detects_changed_rules() {
+        assert!(!declared().is_same_as(&CURRENT.replace("<priority>CRITICAL</priority>", "<priority>MAJOR</priority>")));
+        assert!(!declared().is_same_as(&CURRENT.replace("<value>5</value>", "<value>6</value>")));
+        assert!(!declared().is_same_as(&CURRENT.replace("<key>S124</key>", "<key>S125</key>")));
+        assert!(!declared().is_same_as(&CURRENT.replace("<name>Strict</name>", "<name>Safe</name>")));
+    }
+
+    #[test]
+    fn detects_added_and_removed_rules() {
+        let extra_rule = "<rule><repositoryKey>java</repositoryKey><key>S100</key></rule></rules>";
+
+        assert!(!declared().is_same_as(&CURRENT.replace("</rules>", extra_rule)));
+        assert!(!declared().is_same_as("<profile><name>Strict</name><language>java</language><rules>\
+            <rule><repositoryKey>java</repositoryKey><key>S107</key><priority>CRITICAL</priority>\
+            <parameters><parameter><key>max</key><value>5</value></parameter></parameters></rule>\
+            </rules></profile>"));
+        assert_eq!(declared().rule_keys(), vec!["java:S107", "java:S124"]);
+    }
+
+    #[test]
+    fn deactivates_rules() {
+        let backup = deactivate_rule(DECLARED, "java:S107");
+
+        assert_eq!(QualityProfileBackup { backup, ..declared() }.rule_keys(), vec!["java:S124"]);
+        assert_eq!(deactivate_rule(DECLARED, "java:S100"), DECLARED);
+    }
+}
@@ -0,0 +1,51 @@
+use quality_profile::QualityProfileBackup;
+use report::{Action, ResourceKind};
+use resource::{Change, Resource};
+use sonar_api::SonarApiError;
+use sonar_client::SonarClient;
+
+// The quality profile is restored from its backup when it does not exist or when its rules differ. It's restored after
+// the custom rules it activates.
+pub struct QualityProfileResource {
+    profile: QualityProfileBackup,
+    custom_rules: Vec<String>,
+}
+
+impl QualityProfileResource {
+    pub fn new(profile: QualityProfileBackup, declared_custom_rules: &[String]) -> QualityProfileResource {
+        let custom_rules = profile.rule_keys().into_iter().filter(|key| declared_custom_rules.contains(key)).collect();
+
+        QualityProfileResource { profile, custom_rules }
+    }
+}
+
+impl Resource for QualityProfileResource {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::QualityProfile
+    }
+
+    fn name(&self) -> String {
+        format!("{} ({})", self.profile.name, self.profile.language)
+    }
+
+    fn depends_on(&self) -> Vec<(ResourceKind, String)> {
+        return self.custom_rules.iter().map(|key| (ResourceKind::CustomRule, key.to_string())).collect();
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let profile = &self.profile;
+        let exists = client.get_quality_profiles()?.iter().any(|current| current.name == profile.name && current.language == profile.language);
+
+        let (action, description) = if !exists {
+            (Action::Created, "does not exist".to_string())
+        } else if !profile.is_same_as(&client.backup_quality_profile(&profile.language, &profile.name)?) {
+            (Action::Updated, format!("differs from [{}]", profile.file))
+        } else {
+            return Ok(vec![]);
+        };
+
+        let backup = profile.backup.to_string();
+
+        return Ok(vec![Change::new(action, description, move |client| client.restore_quality_profile(&backup))]);
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Spaces the requests sent to SonarQube, whatever the number of threads sending them.
+pub struct RateLimiter {
+    interval: Duration,
+    // instant from which the next request can be sent
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> RateLimiter {
+        RateLimiter { interval: Duration::from_secs(1) / requests_per_second.max(1), next: Mutex::new(Instant::now()) }
+    }
+
+    // Waits until a request can be sent, the slot is reserved before waiting so that threads are served in turn.
+    pub fn acquire(&self) {
+        let now = Instant::now();
+        let slot = {
+            let mut next = self.next.lock().expect("Cannot read the rate limit.");
+            let slot = (*next).max(now);
+            *next = slot + self.interval;
+
+            slot
+        };
+
+        if slot > now {
+            thread::sleep(slot - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn spaces_requests() {
+        let limiter = RateLimiter::new(20);
+        let started = Instant::now();
+
+        for _ in 0..4 {
+            limiter.acquire();
+        }
+
+        // the first request is sent immediately, the next ones every 50ms
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,526 @@
+use std::cell::RefCell;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use config_file_model::ConfigurationFile;
+use dependency_graph::{check_references, dependency_indexes, dependency_order};
+use quality_profile::QualityProfileBackup;
+use report::{Action, Report};
+use resource::{declared_resources, resulting_action, undeclared_resources, Resource};
+use sonar_api::SonarApiError;
+use sonar_api_model::{SonarCustomRule, SonarGroupCreationRequest, SonarProperty, SonarUser};
+use sonar_client::SonarClient;
+use sonar_snapshot::SonarSnapshot;
+use variable_resolver::VariableResolver;
+
+#[derive(Debug, Default)]
+pub struct ReconcileOptions {
+    // every resource that can be applied is applied, instead of stopping at the first failure
+    pub keep_going: bool,
+
+    // file defining variables of the configuration, environment variables take precedence over them
+    pub env_file: Option<String>,
+
+    // resources of SonarQube that are not declared are deleted
+    pub prune: bool,
+
+    // number of resources applied concurrently, a resource is applied once the resources it depends on are applied
+    pub parallelism: usize,
+}
+
+// Applies the configuration file to SonarQube, the outcome of every resource is recorded in the report. Variables of
+// the configuration are resolved first: a missing variable does not leave SonarQube half-configured. Resources are
+// compared to a snapshot of SonarQube fetched once.
+pub fn reconcile(config_file: ConfigurationFile, client: &dyn SonarClient, options: &ReconcileOptions, report: &mut Report) -> Result<(), SonarApiError> {
+    let resources = resources(config_file, client, options.env_file.as_ref(), options.prune)?;
+    let snapshot = SonarSnapshot::fetch(client)?;
+
+    return apply(&snapshot, &resources, options, report);
+}
+
+// Resources declared by the configuration file, followed by the undeclared ones when pruning, in dependency order.
+// Dangling references are reported before contacting SonarQube.
+pub fn resources(config_file: ConfigurationFile, client: &dyn SonarClient, env_file: Option<&String>, prune: bool) -> Result<Vec<Box<dyn Resource>>, SonarApiError> {
+    let variable_resolver = VariableResolver::new(env_file).map_err(SonarApiError::new)?;
+
+    // the first variable that cannot be resolved is reported once every value is resolved
+    let resolution_error = RefCell::new(None);
+    let resolve_variables = |tokenized: &String| {
+        variable_resolver.try_resolve(tokenized).unwrap_or_else(|error| {
+            resolution_error.borrow_mut().get_or_insert(error);
+            String::new()
+        })
+    };
+
+    let properties = SonarProperty::from_configuration_properties(&config_file.properties.unwrap_or_default(), &resolve_variables);
+    let groups = SonarGroupCreationRequest::from_configuration_groups(&config_file.groups.unwrap_or_default(), &resolve_variables);
+    let users = SonarUser::from_configuration_users(&config_file.users.unwrap_or_default(), &resolve_variables);
+    let custom_rules = SonarCustomRule::from_configuration_custom_rules(&config_file.custom_rules.unwrap_or_default(), &resolve_variables);
+    let quality_profiles = QualityProfileBackup::from_configuration_quality_profiles(&config_file.quality_profiles.unwrap_or_default(), &resolve_variables);
+
+    if let Some(error) = resolution_error.borrow_mut().take() {
+        return Err(SonarApiError::new(error));
+    }
+    let quality_profiles = quality_profiles.map_err(SonarApiError::new)?;
+
+    let mut resources = declared_resources(&properties, &custom_rules, &quality_profiles, &groups, &users);
+    check_references(&resources).map_err(SonarApiError::new)?;
+
+    if prune {
+        resources.extend(undeclared_resources(client, &groups, &custom_rules)?);
+    }
+
+    return dependency_order(resources).map_err(SonarApiError::new);
+}
+
+fn apply(client: &dyn SonarClient, resources: &[Box<dyn Resource>], options: &ReconcileOptions, report: &mut Report) -> Result<(), SonarApiError> {
+    let outcomes = execute(client, resources, options.keep_going, options.parallelism.max(1));
+
+    // outcomes are recorded in the order of the resources, whatever the order in which they were applied
+    let mut first_error = None;
+    for (resource, outcome) in resources.iter().zip(outcomes) {
+        // not applied since a resource failed (fail-fast)
+        if let Some(outcome) = outcome {
+            if let Err(error) = report.record(resource.kind(), resource.name(), outcome.result, outcome.duration_ms) {
+                first_error = first_error.or(Some(error));
+            }
+        }
+    }
+
+    if let Some(error) = first_error.filter(|_| !options.keep_going) {
+        return Err(error);
+    }
+
+    let failed = report.summary().failed;
+    if failed > 0 {
+        return Err(SonarApiError::new(format!("{} resource(s) cannot be applied", failed)));
+    }
+
+    Ok(())
+}
+
+struct Outcome {
+    result: Result<Action, SonarApiError>,
+    duration_ms: u64,
+}
+
+// State shared by the threads applying the resources.
+struct Schedule {
+    started: Vec<bool>,
+    finished: Vec<bool>,
+    failed: Vec<bool>,
+    outcomes: Vec<Option<Outcome>>,
+    // set after the first failure in fail-fast mode, no other resource is started
+    stopped: bool,
+}
+
+impl Schedule {
+    // The first resource not started whose dependencies are applied. Resources to delete are applied one after the
+    // other, once every previous resource is applied.
+    fn next(&self, resources: &[Box<dyn Resource>], dependencies: &[Vec<usize>]) -> Option<usize> {
+        return (0..resources.len()).find(|index| {
+            !self.started[*index] && if resources[*index].is_absent() {
+                self.finished[..*index].iter().all(|finished| *finished)
+            } else {
+                dependencies[*index].iter().all(|dependency| self.finished[*dependency])
+            }
+        });
+    }
+}
+
+// Applies the resources with the given number of threads, a resource is applied once the resources it depends on are
+// applied. With a single thread, resources are applied in their order.
+fn execute(client: &dyn SonarClient, resources: &[Box<dyn Resource>], keep_going: bool, parallelism: usize) -> Vec<Option<Outcome>> {
+    let dependencies = dependency_indexes(resources);
+    let schedule = Mutex::new(Schedule {
+        started: vec![false; resources.len()],
+        finished: vec![false; resources.len()],
+        failed: vec![false; resources.len()],
+        outcomes: resources.iter().map(|_| None).collect(),
+        stopped: false,
+    });
+    let changed = Condvar::new();
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.min(resources.len()) {
+            scope.spawn(|| work(client, resources, &dependencies, keep_going, &schedule, &changed));
+        }
+    });
+
+    return schedule.into_inner().expect("Cannot read the outcomes.").outcomes;
+}
+
+// Applies resources as long as there are resources ready to be applied.
+fn work(client: &dyn SonarClient, resources: &[Box<dyn Resource>], dependencies: &[Vec<usize>], keep_going: bool, schedule: &Mutex<Schedule>, changed: &Condvar) {
+    loop {
+        let (index, failed_dependency) = {
+            let mut state = schedule.lock().expect("Cannot read the schedule.");
+
+            loop {
+                if state.stopped || state.started.iter().all(|started| *started) {
+                    return;
+                }
+
+                if let Some(index) = state.next(resources, dependencies) {
+                    state.started[index] = true;
+
+                    break (index, dependencies[index].iter().find(|dependency| state.failed[**dependency]).cloned());
+                }
+
+                state = changed.wait(state).expect("Cannot read the schedule.");
+            }
+        };
+
+        let started = Instant::now();
+        let result = match failed_dependency {
+            Some(dependency) => Err(SonarApiError::new(format!("Skipped, the {} [{}] cannot be applied", resources[dependency].kind(), resources[dependency].name()))),
+            None => apply_resource(client, resources[index].as_ref()),
+        };
+
+        let mut state = schedule.lock().expect("Cannot read the schedule.");
+        state.finished[index] = true;
+        state.failed[index] = result.is_err();
+        state.stopped |= result.is_err() && !keep_going;
+        state.outcomes[index] = Some(Outcome { result, duration_ms: started.elapsed().as_millis() as u64 });
+
+        changed.notify_all();
+    }
+}
+
+fn apply_resource(client: &dyn SonarClient, resource: &dyn Resource) -> Result<Action, SonarApiError> {
+    debug!("Applying {} [{}].", resource.kind(), resource.name());
+
+    let changes = resource.diff(client)?;
+
+    for change in &changes {
+        change.apply(client)?;
+    }
+
+    return Ok(resulting_action(&changes));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use serde_json;
+    use serde_yaml;
+
+    use super::{reconcile, ReconcileOptions};
+    use in_memory_sonar::InMemorySonar;
+    use report::Report;
+    use sonar_api_model::SonarUser;
+    use sonar_client::SonarClient;
+
+    fn apply(sonar: &InMemorySonar, configuration: &str, options: &ReconcileOptions) -> Result<(), String> {
+        return reconcile(serde_yaml::from_str(configuration).unwrap(), sonar, options, &mut Report::new("setup"))
+            .map_err(|error| error.to_string());
+    }
+
+    fn writes_after(sonar: &InMemorySonar, configuration: &str) -> Vec<String> {
+        let before = sonar.writes().len();
+
+        apply(sonar, configuration, &ReconcileOptions::default()).unwrap();
+
+        return sonar.writes().split_off(before);
+    }
+
+    const DEVELOPERS: &str = r#"
+groups:
+  - name: developers
+    description: Developers
+    permissions: [codeviewer, user]
+users:
+  - login: jdoe
+    name: John Doe
+    password: secret
+    passwordPolicy: createOnly
+    groups: [developers]
+"#;
+
+    #[test]
+    fn creates_groups_users_and_memberships() {
+        let sonar = InMemorySonar::new();
+
+        apply(&sonar, DEVELOPERS, &ReconcileOptions::default()).unwrap();
+
+        assert!(sonar.get_user("jdoe").unwrap().is_some_and(|user| user.is_active()));
+        assert_eq!(sonar.get_user_groups("jdoe").unwrap(), vec!["developers", "sonar-users"]);
+
+        let template_groups = sonar.get_template_groups().unwrap();
+        let developers = template_groups.iter().find(|group| group.name == "developers").unwrap();
+        assert_eq!(developers.permissions, vec!["codeviewer", "user"]);
+    }
+
+    #[test]
+    fn applies_again_without_writes() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, DEVELOPERS, &ReconcileOptions::default()).unwrap();
+
+        assert!(writes_after(&sonar, DEVELOPERS).is_empty());
+    }
+
+    #[test]
+    fn reports_unchanged_permissions_on_the_second_run() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, DEVELOPERS, &ReconcileOptions::default()).unwrap();
+
+        let mut report = Report::new("setup");
+        reconcile(serde_yaml::from_str(DEVELOPERS).unwrap(), &sonar, &ReconcileOptions::default(), &mut report).unwrap();
+
+        let document: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        let permissions: Vec<&serde_json::Value> = document["resources"].as_array().unwrap().iter().filter(|resource| resource["kind"] == "permission").collect();
+
+        assert_eq!(permissions.len(), 1);
+        assert_eq!(permissions[0]["action"], "unchanged");
+        assert_eq!(report.summary().unchanged, document["resources"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn only_changes_permissions_that_differ() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, DEVELOPERS, &ReconcileOptions::default()).unwrap();
+
+        let writes = writes_after(&sonar, &DEVELOPERS.replace("[codeviewer, user]", "[codeviewer, scan]"));
+
+        assert_eq!(writes, vec!["remove permission user from developers", "add permission scan to developers"]);
+    }
+
+    #[test]
+    fn changes_the_channel_of_notifications() {
+        let sonar = InMemorySonar::new();
+        let configuration = format!("{}    notifications:\n      - type: ChangesOnMyIssue\n        project: my-project\n", DEVELOPERS);
+        apply(&sonar, &configuration, &ReconcileOptions::default()).unwrap();
+
+        let writes = writes_after(&sonar, &format!("{}        channel: SlackChannel\n", configuration));
+
+        assert_eq!(writes, vec![
+            "remove notification ChangesOnMyIssue/my-project@EmailNotificationChannel from jdoe",
+            "add notification ChangesOnMyIssue/my-project@SlackChannel to jdoe",
+        ]);
+    }
+
+    #[test]
+    fn prunes_undeclared_groups_but_built_in_ones() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, DEVELOPERS, &ReconcileOptions::default()).unwrap();
+
+        apply(&sonar, "users: []", &ReconcileOptions { prune: true, ..ReconcileOptions::default() }).unwrap();
+
+        let groups: Vec<String> = sonar.get_groups().unwrap().into_iter().map(|group| group.name).collect();
+        assert_eq!(groups, vec!["sonar-administrators", "sonar-users"]);
+        assert_eq!(sonar.get_user_groups("jdoe").unwrap(), vec!["sonar-users"]);
+    }
+
+    #[test]
+    fn removes_undeclared_memberships_except_default_group() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, DEVELOPERS, &ReconcileOptions::default()).unwrap();
+
+        let writes = writes_after(&sonar, &DEVELOPERS.replace("groups: [developers]", "groups: []"));
+
+        assert!(writes.contains(&"remove jdoe from developers".to_string()));
+        assert_eq!(sonar.get_user_groups("jdoe").unwrap(), vec!["sonar-users"]);
+    }
+
+    #[test]
+    fn adds_external_members_of_groups() {
+        let sonar = InMemorySonar::new();
+        sonar.create_user(&SonarUser { local: Some(false), ..serde_yaml::from_str("{login: ldap-user, name: LDAP user, groups: []}").unwrap() }).unwrap();
+
+        apply(&sonar, r#"
+groups:
+  - name: developers
+    description: Developers
+    permissions: []
+    members: [ldap-user]
+"#, &ReconcileOptions::default()).unwrap();
+
+        assert_eq!(sonar.get_group_members("developers").unwrap(), vec!["ldap-user"]);
+    }
+
+    #[test]
+    fn rejects_dangling_references_before_any_call() {
+        let sonar = InMemorySonar::new();
+
+        let error = apply(&sonar, &DEVELOPERS.replace("groups: [developers]", "groups: [testers]"), &ReconcileOptions::default()).unwrap_err();
+
+        assert_eq!(error, "membership [jdoe] references the group [testers] that is not declared");
+        assert!(sonar.writes().is_empty());
+    }
+
+    #[test]
+    fn reads_quality_profiles_before_any_call() {
+        let sonar = InMemorySonar::new();
+
+        let error = apply(&sonar, &format!("{}qualityProfiles:\n  - file: /missing/strict.xml\n", DEVELOPERS), &ReconcileOptions::default()).unwrap_err();
+
+        assert!(error.starts_with("Cannot read the quality profile [/missing/strict.xml]"), "{}", error);
+        assert!(sonar.writes().is_empty());
+    }
+
+    #[test]
+    fn restores_quality_profiles_that_differ() {
+        let file = env::temp_dir().join(format!("sonar-as-code-reconcile-{}.xml", std::process::id()));
+        fs::write(&file, "<profile><name>Strict</name><language>java</language><rules/></profile>").unwrap();
+        let configuration = format!("qualityProfiles:\n  - file: {}\n", file.display());
+        let sonar = InMemorySonar::new();
+
+        let first = writes_after(&sonar, &configuration);
+        let second = writes_after(&sonar, &configuration);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(first, vec!["restore quality profile Strict of java"]);
+        assert!(second.is_empty(), "{:?}", second);
+    }
+
+    const NO_TODO: &str = r#"
+customRules:
+  - templateKey: java:S124
+    customKey: no-todo
+    name: No TODO
+    markdownDescription: TODO comments must be tracked as issues
+    params:
+      regularExpression: .*TODO.*
+"#;
+
+    #[test]
+    fn reconciles_custom_rules() {
+        let sonar = InMemorySonar::new();
+
+        assert_eq!(writes_after(&sonar, NO_TODO), vec!["create rule java:no-todo"]);
+        assert!(writes_after(&sonar, NO_TODO).is_empty());
+        assert_eq!(writes_after(&sonar, &NO_TODO.replace(".*TODO.*", ".*(TODO|FIXME).*")), vec!["update rule java:no-todo"]);
+
+        // the type cannot be updated, the rule is created again
+        assert_eq!(writes_after(&sonar, &format!("{}    type: BUG\n", NO_TODO)), vec!["delete rule java:no-todo", "create rule java:no-todo"]);
+    }
+
+    #[test]
+    fn prunes_undeclared_custom_rules() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, NO_TODO, &ReconcileOptions::default()).unwrap();
+
+        apply(&sonar, "{}", &ReconcileOptions { prune: true, ..ReconcileOptions::default() }).unwrap();
+
+        assert!(sonar.get_rule("java:no-todo").unwrap().is_some_and(|rule| rule.is_removed()));
+    }
+
+    #[test]
+    fn restores_quality_profiles_after_their_custom_rules() {
+        let file = env::temp_dir().join(format!("sonar-as-code-custom-rules-{}.xml", std::process::id()));
+        fs::write(&file, "<profile><name>Strict</name><language>java</language><rules>\
+            <rule><repositoryKey>java</repositoryKey><key>no-todo</key><priority>MAJOR</priority></rule>\
+            </rules></profile>").unwrap();
+        let configuration = format!("qualityProfiles:\n  - file: {}\n{}", file.display(), NO_TODO);
+        let sonar = InMemorySonar::new();
+
+        apply(&sonar, &configuration, &ReconcileOptions { parallelism: 4, ..ReconcileOptions::default() }).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(sonar.writes(), vec!["create rule java:no-todo", "restore quality profile Strict of java"]);
+    }
+
+    #[test]
+    fn activates_recreated_custom_rules_again_in_their_quality_profiles() {
+        let file = env::temp_dir().join(format!("sonar-as-code-recreated-rules-{}.xml", std::process::id()));
+        fs::write(&file, "<profile><name>Strict</name><language>java</language><rules>\
+            <rule><repositoryKey>java</repositoryKey><key>no-todo</key><priority>MAJOR</priority></rule>\
+            </rules></profile>").unwrap();
+        let configuration = format!("qualityProfiles:\n  - file: {}\n{}", file.display(), NO_TODO);
+        let sonar = InMemorySonar::new();
+        apply(&sonar, &configuration, &ReconcileOptions::default()).unwrap();
+
+        // deleting the rule deactivates it in the profile, the profile is restored in the same run
+        let writes = writes_after(&sonar, &format!("{}    type: BUG\n", configuration));
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(writes, vec!["delete rule java:no-todo", "create rule java:no-todo", "restore quality profile Strict of java"]);
+        assert!(sonar.backup_quality_profile("java", "Strict").unwrap().contains("<key>no-todo</key>"));
+    }
+
+    #[test]
+    fn deactivates_user() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, DEVELOPERS, &ReconcileOptions::default()).unwrap();
+
+        apply(&sonar, &format!("{}    state: deactivated\n", DEVELOPERS.replace("    groups: [developers]\n", "")), &ReconcileOptions::default()).unwrap();
+
+        assert!(!sonar.get_user("jdoe").unwrap().unwrap().is_active());
+        assert!(sonar.get_user_groups("jdoe").unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_going_after_a_failure() {
+        let sonar = InMemorySonar::new();
+        let options = ReconcileOptions { keep_going: true, ..ReconcileOptions::default() };
+
+        let error = apply(&sonar, r#"
+users:
+  - login: jdoe
+    name: John Doe
+  - login: jsmith
+    name: Jane Smith
+    password: secret
+"#, &options).unwrap_err();
+
+        // the user and its memberships, skipped
+        assert_eq!(error, "2 resource(s) cannot be applied");
+        assert!(sonar.get_user("jdoe").unwrap().is_none());
+        assert!(sonar.get_user("jsmith").unwrap().is_some());
+    }
+
+    // kind, name and action of every resource of the report
+    fn outcomes(report: &Report) -> Vec<String> {
+        let document: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+
+        return document["resources"].as_array().unwrap().iter()
+            .map(|resource| format!("{} {} {}", resource["kind"], resource["name"], resource["action"]))
+            .collect();
+    }
+
+    fn permissions(sonar: &InMemorySonar) -> Vec<(String, Vec<String>)> {
+        let mut permissions: Vec<(String, Vec<String>)> = sonar.get_template_groups().unwrap().into_iter()
+            .map(|group| (group.name, group.permissions))
+            .collect();
+        permissions.sort();
+
+        return permissions;
+    }
+
+    #[test]
+    fn applies_concurrently_with_the_same_outcome() {
+        let mut configuration = String::from("groups:\n");
+        for group in 0..5 {
+            configuration.push_str(&format!("  - name: group-{}\n    description: Group {}\n    permissions: [codeviewer]\n", group, group));
+        }
+        configuration.push_str("users:\n");
+        for user in 0..10 {
+            configuration.push_str(&format!("  - login: user-{}\n    name: User {}\n    password: secret\n    passwordPolicy: createOnly\n    groups: [group-{}]\n", user, user, user % 5));
+        }
+
+        let sequential = InMemorySonar::new();
+        let mut sequential_report = Report::new("setup");
+        reconcile(serde_yaml::from_str(&configuration).unwrap(), &sequential, &ReconcileOptions::default(), &mut sequential_report).unwrap();
+
+        let concurrent = InMemorySonar::new();
+        let mut concurrent_report = Report::new("setup");
+        let options = ReconcileOptions { parallelism: 4, ..ReconcileOptions::default() };
+        reconcile(serde_yaml::from_str(&configuration).unwrap(), &concurrent, &options, &mut concurrent_report).unwrap();
+
+        assert_eq!(outcomes(&concurrent_report), outcomes(&sequential_report));
+        assert_eq!(permissions(&concurrent), permissions(&sequential));
+        for user in 0..10 {
+            let login = format!("user-{}", user);
+            assert_eq!(concurrent.get_user_groups(&login).unwrap(), sequential.get_user_groups(&login).unwrap());
+        }
+
+        let mut concurrent_writes = concurrent.writes();
+        let mut sequential_writes = sequential.writes();
+        concurrent_writes.sort();
+        sequential_writes.sort();
+        assert_eq!(concurrent_writes, sequential_writes);
+    }
+}
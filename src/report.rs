@@ -0,0 +1,252 @@
+use std::fmt;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    Property,
+    Group,
+    Permission,
+    User,
+    Membership,
+    Notification,
+    #[serde(rename = "customRule")]
+    CustomRule,
+    #[serde(rename = "qualityProfile")]
+    QualityProfile,
+}
+
+impl ResourceKind {
+    // Name of the kind in the JSON report, e.g. customRule.
+    pub fn key(&self) -> &'static str {
+        match self {
+            ResourceKind::Property => "property",
+            ResourceKind::Group => "group",
+            ResourceKind::Permission => "permission",
+            ResourceKind::User => "user",
+            ResourceKind::Membership => "membership",
+            ResourceKind::Notification => "notification",
+            ResourceKind::CustomRule => "customRule",
+            ResourceKind::QualityProfile => "qualityProfile",
+        }
+    }
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ResourceKind::Property => "property",
+            ResourceKind::Group => "group",
+            ResourceKind::Permission => "permission",
+            ResourceKind::User => "user",
+            ResourceKind::Membership => "membership",
+            ResourceKind::Notification => "notification",
+            ResourceKind::CustomRule => "custom rule",
+            ResourceKind::QualityProfile => "quality profile",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Created,
+    Updated,
+    Unchanged,
+    Deleted,
+    Validated,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceOutcome {
+    pub kind: ResourceKind,
+    pub name: String,
+    pub action: Action,
+    pub duration_ms: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReportSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+    pub validated: usize,
+    pub failed: usize,
+}
+
+// Outcome of every resource touched while running a command (applying or validating the configuration).
+pub struct Report {
+    command: String,
+    started: Instant,
+    resources: Vec<ResourceOutcome>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportDocument<'a> {
+    command: &'a str,
+    success: bool,
+    duration_ms: u64,
+    summary: ReportSummary,
+    resources: &'a Vec<ResourceOutcome>,
+}
+
+impl Report {
+    pub fn new(command: &str) -> Report {
+        Report { command: command.to_string(), started: Instant::now(), resources: Vec::new() }
+    }
+
+    // Executes the operation on the resource and records its outcome.
+    pub fn track<E: fmt::Display, F: FnOnce() -> Result<Action, E>>(&mut self, kind: ResourceKind, name: String, operation: F) -> Result<Action, E> {
+        let started = Instant::now();
+        let result = operation();
+
+        return self.record(kind, name, result, started.elapsed().as_millis() as u64);
+    }
+
+    // Records the outcome of an operation executed elsewhere (e.g. by another thread).
+    pub fn record<E: fmt::Display>(&mut self, kind: ResourceKind, name: String, result: Result<Action, E>, duration_ms: u64) -> Result<Action, E> {
+        self.resources.push(ResourceOutcome {
+            kind,
+            name,
+            action: *result.as_ref().unwrap_or(&Action::Failed),
+            duration_ms,
+            error: result.as_ref().err().map(|error| error.to_string()),
+        });
+
+        return result;
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item=&ResourceOutcome> {
+        self.resources.iter().filter(|resource| resource.action == Action::Failed)
+    }
+
+    pub fn summary(&self) -> ReportSummary {
+        let mut summary = ReportSummary::default();
+
+        for resource in &self.resources {
+            match resource.action {
+                Action::Created => summary.created += 1,
+                Action::Updated => summary.updated += 1,
+                Action::Unchanged => summary.unchanged += 1,
+                Action::Deleted => summary.deleted += 1,
+                Action::Validated => summary.validated += 1,
+                Action::Failed => summary.failed += 1,
+            }
+        }
+
+        return summary;
+    }
+
+    pub fn to_json(&self) -> String {
+        let summary = self.summary();
+
+        let document = ReportDocument {
+            command: &self.command,
+            success: summary.failed == 0,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            summary,
+            resources: &self.resources,
+        };
+
+        return serde_json::to_string_pretty(&document).expect("Cannot serialize the report.");
+    }
+
+    // Every resource is a test case of the command test suite, failed resources have a failure with the error message.
+    pub fn to_junit(&self) -> String {
+        let failures = self.resources.iter().filter(|resource| resource.action == Action::Failed).count();
+        let time = format_seconds(self.started.elapsed().as_millis() as u64);
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"sonar-as-code\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{}\">\n",
+            self.resources.len(), failures, time
+        ));
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"0\" time=\"{}\">\n",
+            escape_xml(&self.command), self.resources.len(), failures, time
+        ));
+
+        for resource in &self.resources {
+            let test_case = format!(
+                "    <testcase classname=\"{}.{}\" name=\"{}/{}\" time=\"{}\"",
+                escape_xml(&self.command), resource.kind.key(), resource.kind, escape_xml(&resource.name), format_seconds(resource.duration_ms)
+            );
+
+            match &resource.error {
+                Some(error) => {
+                    xml.push_str(&format!(
+                        "{}>\n      <failure message=\"{}\" type=\"{}\">{}</failure>\n    </testcase>\n",
+                        test_case, escape_xml(error), escape_xml(&self.command), escape_xml(error)
+                    ));
+                }
+                None => {
+                    xml.push_str(&format!("{}/>\n", test_case));
+                }
+            }
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+
+        return xml;
+    }
+}
+
+fn format_seconds(duration_ms: u64) -> String {
+    format!("{}.{:03}", duration_ms / 1000, duration_ms % 1000)
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_the_outcomes() {
+        let mut report = Report::new("setup");
+
+        let _ = report.track::<String, _>(ResourceKind::Group, "developers".to_string(), || Ok(Action::Created));
+        let _ = report.track::<String, _>(ResourceKind::Group, "testers".to_string(), || Ok(Action::Unchanged));
+        let _ = report.track(ResourceKind::User, "jdoe".to_string(), || Err("Cannot create the user"));
+
+        let summary = report.summary();
+        assert_eq!((summary.created, summary.unchanged, summary.failed), (1, 1, 1));
+        assert_eq!(report.failures().map(|failure| failure.name.as_str()).collect::<Vec<_>>(), vec!["jdoe"]);
+    }
+
+    #[test]
+    fn escapes_failures_in_junit() {
+        let mut report = Report::new("set<up>");
+
+        let _ = report.track(ResourceKind::Group, "R&D \"core\" <team>".to_string(), || Err("Invalid name [R&D \"core\" <team>]"));
+
+        let junit = report.to_junit();
+
+        assert!(junit.contains("<testsuite name=\"set&lt;up&gt;\" tests=\"1\" failures=\"1\""), "{}", junit);
+        assert!(junit.contains("<testcase classname=\"set&lt;up&gt;.group\" name=\"group/R&amp;D &quot;core&quot; &lt;team&gt;\""), "{}", junit);
+        assert!(junit.contains("<failure message=\"Invalid name [R&amp;D &quot;core&quot; &lt;team&gt;]\" type=\"set&lt;up&gt;\">Invalid name [R&amp;D &quot;core&quot; &lt;team&gt;]</failure>"), "{}", junit);
+    }
+
+    #[test]
+    fn names_junit_classes_after_the_kind_key() {
+        let mut report = Report::new("setup");
+
+        let _ = report.track(ResourceKind::CustomRule, "java:no-todo".to_string(), || Ok::<Action, String>(Action::Created));
+
+        assert!(report.to_junit().contains("<testcase classname=\"setup.customRule\" name=\"custom rule/java:no-todo\""), "{}", report.to_junit());
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+use sonar_as_code::report::Report;
+
+#[derive(StructOpt, Debug)]
+pub struct ReportOptions {
+    #[structopt(name = "report", long = "report", about = "file where the JSON report of every resource touched is written")]
+    pub report: Option<String>,
+
+    #[structopt(name = "output", long = "output", default_value = "text", about = "format of the standard output: text or json (the report)")]
+    pub output: OutputFormat,
+
+    #[structopt(name = "junit", long = "junit", about = "file where the JUnit XML report is written, every resource is a test case")]
+    pub junit: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unsupported output [{}], expecting text or json.", value)),
+        }
+    }
+}
+
+impl ReportOptions {
+    pub fn publish(&self, report: &Report) -> Result<(), String> {
+        if let Some(file) = &self.report {
+            debug!("Writing report to [{}].", file);
+
+            fs::write(file, report.to_json())
+                .map_err(|error| format!("Cannot write the report [{}]: {}", file, error))?;
+        }
+
+        if let Some(file) = &self.junit {
+            debug!("Writing JUnit report to [{}].", file);
+
+            fs::write(file, report.to_junit())
+                .map_err(|error| format!("Cannot write the JUnit report [{}]: {}", file, error))?;
+        }
+
+        if self.output == OutputFormat::Json {
+            println!("{}", report.to_json());
+        }
+
+        return Ok(());
+    }
+}
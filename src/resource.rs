@@ -0,0 +1,185 @@
+use std::fmt;
+
+use custom_rule_resource::{AbsentCustomRule, CustomRuleResource};
+use group_resource::{AbsentGroup, GroupMembers, GroupPermissions, GroupResource};
+use membership::{desired_user_groups, unmanaged_group_members};
+use property_resource::PropertyResource;
+use quality_profile::QualityProfileBackup;
+use quality_profile_resource::QualityProfileResource;
+use report::{Action, ResourceKind};
+use sonar_api::{is_built_in_group, SonarApiError, ADMIN_USER, DEFAULT_GROUP};
+use sonar_api_model::{SonarCustomRule, SonarGroupCreationRequest, SonarProperty, SonarUser};
+use sonar_client::SonarClient;
+use user_resource::{UserGroups, UserNotifications, UserResource};
+
+// Something declared in the configuration file and reconciled on SonarQube. Its current state is read and compared to
+// the declared one, the changes converging SonarQube are returned: the same diff is applied by the setup and reported
+// by the check. Independent resources may be reconciled concurrently.
+pub trait Resource: Sync {
+    fn kind(&self) -> ResourceKind;
+
+    fn name(&self) -> String;
+
+    // Resources that must exist before this one, it's skipped when one of them cannot be applied.
+    fn depends_on(&self) -> Vec<(ResourceKind, String)> {
+        return vec![];
+    }
+
+    // Resource that must not exist, it's deleted after the other ones are applied.
+    fn is_absent(&self) -> bool {
+        return false;
+    }
+
+    // Changes to apply in order, none when SonarQube is in sync with the resource.
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError>;
+}
+
+type Operation = Box<dyn Fn(&dyn SonarClient) -> Result<(), SonarApiError>>;
+
+// Call converging a resource, described for the check and the logs.
+pub struct Change {
+    pub action: Action,
+    pub description: String,
+    // false when the current state cannot be read (e.g. secured values, passwords), it's applied anyway but it's not a drift
+    pub drift: bool,
+    operation: Operation,
+}
+
+impl Change {
+    pub fn new<F: Fn(&dyn SonarClient) -> Result<(), SonarApiError> + 'static>(action: Action, description: String, operation: F) -> Change {
+        Change { action, description, drift: true, operation: Box::new(operation) }
+    }
+
+    // Change always applied since the current state cannot be compared.
+    pub fn blind<F: Fn(&dyn SonarClient) -> Result<(), SonarApiError> + 'static>(action: Action, description: String, operation: F) -> Change {
+        Change { action, description, drift: false, operation: Box::new(operation) }
+    }
+
+    pub fn apply(&self, client: &dyn SonarClient) -> Result<(), SonarApiError> {
+        debug!("Applying change [{}].", self.description);
+
+        return (self.operation)(client);
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+// Outcome of the resource: created or deleted when one of its changes did it, updated when it only changed.
+pub fn resulting_action(changes: &[Change]) -> Action {
+    if changes.is_empty() {
+        return Action::Unchanged;
+    }
+
+    for action in &[Action::Created, Action::Deleted] {
+        if changes.iter().any(|change| change.action == *action) {
+            return *action;
+        }
+    }
+
+    return Action::Updated;
+}
+
+// Resources declared by the configuration, in the declaration order.
+pub fn declared_resources(properties: &[SonarProperty],
+                          custom_rules: &[SonarCustomRule],
+                          quality_profiles: &[QualityProfileBackup],
+                          groups: &[SonarGroupCreationRequest],
+                          users: &[SonarUser]) -> Vec<Box<dyn Resource>> {
+    let mut resources: Vec<Box<dyn Resource>> = Vec::new();
+
+    for property in properties {
+        resources.push(Box::new(PropertyResource::new(property.clone())));
+    }
+
+    for custom_rule in custom_rules {
+        resources.push(Box::new(CustomRuleResource::new(custom_rule.clone())));
+    }
+
+    let custom_rule_keys: Vec<String> = custom_rules.iter().map(SonarCustomRule::key).collect();
+    for quality_profile in quality_profiles {
+        resources.push(Box::new(QualityProfileResource::new(quality_profile.clone(), &custom_rule_keys)));
+    }
+
+    for group in groups {
+        resources.push(Box::new(GroupResource::new(group.clone())));
+        resources.push(Box::new(GroupPermissions::new(&group.name, &group.permissions)));
+    }
+
+    for user in users {
+        resources.push(Box::new(UserResource::new(user.clone())));
+
+        // deactivated users are not member of any group and don't receive notifications
+        if !user.is_active() {
+            continue;
+        }
+
+        if let Some(notifications) = &user.notifications {
+            resources.push(Box::new(UserNotifications::new(&user.login, notifications)));
+        }
+
+        // groups of the administrator are not managed, every user is member of the default group
+        if user.login != ADMIN_USER {
+            resources.push(Box::new(UserGroups::new(&user.login, &desired_user_groups(user, groups), &[DEFAULT_GROUP])));
+        }
+    }
+
+    // members declared on groups that are not declared as users (e.g. provisioned by LDAP/SAML)
+    let unmanaged_members = unmanaged_group_members(users, ADMIN_USER);
+    for group in groups {
+        if let Some(members) = &group.members {
+            resources.push(Box::new(GroupMembers::new(&group.name, members, &unmanaged_members)));
+        }
+    }
+
+    return resources;
+}
+
+// Resources of SonarQube that are not declared, they are deleted when pruning: groups (except built-in ones) and custom
+// rules.
+pub fn undeclared_resources(client: &dyn SonarClient, groups: &[SonarGroupCreationRequest], custom_rules: &[SonarCustomRule]) -> Result<Vec<Box<dyn Resource>>, SonarApiError> {
+    let mut resources: Vec<Box<dyn Resource>> = Vec::new();
+
+    for group in client.get_groups()? {
+        if is_built_in_group(&group.name) || groups.iter().any(|declared| declared.name == group.name) {
+            continue;
+        }
+
+        resources.push(Box::new(AbsentGroup::new(&group.name)));
+    }
+
+    for rule in client.get_custom_rules()? {
+        if custom_rules.iter().any(|declared| declared.key() == rule.key) {
+            continue;
+        }
+
+        resources.push(Box::new(AbsentCustomRule::new(&rule.key)));
+    }
+
+    return Ok(resources);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resulting_action, Change};
+    use report::Action;
+
+    fn change(action: Action) -> Change {
+        Change::new(action, format!("{:?}", action), |_| Ok(()))
+    }
+
+    #[test]
+    fn resource_without_change_is_unchanged() {
+        assert_eq!(resulting_action(&[]), Action::Unchanged);
+    }
+
+    #[test]
+    fn resource_created_or_deleted_by_a_change() {
+        assert_eq!(resulting_action(&[change(Action::Created), change(Action::Updated)]), Action::Created);
+        assert_eq!(resulting_action(&[change(Action::Updated), change(Action::Deleted)]), Action::Deleted);
+        assert_eq!(resulting_action(&[change(Action::Updated), change(Action::Updated)]), Action::Updated);
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use structopt::StructOpt;
+
+use report_options::ReportOptions;
+use sonar_as_code::reconcile::ReconcileOptions;
+use sonar_as_code::report::Report;
+use sonar_as_code::secret_string::SecretString;
+use sonar_as_code::server_backup::restore as restore_server;
+use sonar_as_code::sonar_api::{SonarApi, SonarApiError};
+
+#[derive(StructOpt, Debug)]
+pub struct RestoreCmd {
+    #[structopt(name = "directory", short = "d", long = "directory", about = "backup directory written by the backup command")]
+    directory: String,
+
+    #[structopt(name = "sonarUrl", short = "s", about = "URL of SonarQube")]
+    url: String,
+
+    #[structopt(name = "username", short = "u", default_value = "admin", about = "Username of the administrator")]
+    username: String,
+
+    #[structopt(name = "password", short = "p", default_value = "admin", about = "Password of the administrator")]
+    password: SecretString,
+
+    #[structopt(name = "nbAttempts", short = "a", default_value = "600", about = "Number of attemps to connect to the API (1sec between attempts)")]
+    number_attempts: usize,
+
+    #[structopt(name = "keepGoing", long = "keep-going", about = "restore every resource that can be restored, failures are summarized at the end (fail-fast by default)")]
+    keep_going: bool,
+
+    #[structopt(flatten)]
+    report: ReportOptions,
+}
+
+// Restores a backup on SonarQube: its quality profiles, then its configuration like the setup does.
+pub fn restore(cmd: RestoreCmd) -> Result<(), SonarApiError> {
+    let options = ReconcileOptions { keep_going: cmd.keep_going, ..ReconcileOptions::default() };
+
+    let sonar_api = SonarApi::new(cmd.url.to_string(), cmd.username.to_string(), cmd.password.clone(), cmd.number_attempts);
+
+    sonar_api.wait_ready()?;
+
+    let mut report = Report::new("restore");
+    let result = restore_server(&sonar_api, Path::new(&cmd.directory), &options, &mut report);
+
+    cmd.report.publish(&report).map_err(SonarApiError::new)?;
+
+    if cmd.keep_going {
+        for failure in report.failures() {
+            eprintln!("Failed {}/{}: {}.", failure.kind, failure.name, failure.error.as_deref().unwrap_or_default());
+        }
+    }
+
+    return result;
+}
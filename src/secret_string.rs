@@ -0,0 +1,134 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const MASK: &str = "******";
+
+// Suffixes of property names considered as sensitive, their values are never displayed.
+const SENSITIVE_PROPERTY_SUFFIXES: [&str; 4] = [".secured", ".secret", ".password", ".token"];
+
+// String that may hold a secret (password, token, sensitive property). When it does, its value is masked
+// when displayed (logs, panic messages, plan). The value is only accessible through expose().
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SecretString {
+    value: String,
+    secret: bool,
+}
+
+impl SecretString {
+    pub fn secret(value: String) -> SecretString {
+        SecretString { value, secret: true }
+    }
+
+    pub fn plain(value: String) -> SecretString {
+        SecretString { value, secret: false }
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.secret
+    }
+
+    pub fn map(&self, mapper: &dyn Fn(&String) -> String) -> SecretString {
+        SecretString { value: mapper(&self.value), secret: self.secret }
+    }
+}
+
+pub fn is_sensitive_property(name: &str) -> bool {
+    SENSITIVE_PROPERTY_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.secret {
+            f.write_str(MASK)
+        } else {
+            f.write_str(&self.value)
+        }
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.secret {
+            f.write_str(MASK)
+        } else {
+            fmt::Debug::fmt(&self.value, f)
+        }
+    }
+}
+
+impl FromStr for SecretString {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(SecretString::secret(value.to_string()))
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.value)
+    }
+}
+
+// Deserialized values are secrets, fields that are only sensitive in some cases (like properties) are wrapped afterwards.
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretString::secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_secrets() {
+        let secret = SecretString::secret("s3cr3t".to_string());
+
+        assert_eq!(format!("{}", secret), "******");
+        assert_eq!(format!("{:?}", secret), "******");
+        assert_eq!(format!("{:?}", Some(secret.clone())), "Some(******)");
+        assert_eq!(secret.expose(), "s3cr3t");
+    }
+
+    #[test]
+    fn displays_plain_values() {
+        let plain = SecretString::plain("https://sonar".to_string());
+
+        assert_eq!(format!("{}", plain), "https://sonar");
+        assert_eq!(format!("{:?}", plain), "\"https://sonar\"");
+    }
+
+    #[test]
+    fn detects_sensitive_properties() {
+        assert!(is_sensitive_property("sonar.auth.github.clientSecret.secured"));
+        assert!(is_sensitive_property("sonar.auth.saml.secret"));
+        assert!(is_sensitive_property("email.smtp.password"));
+        assert!(is_sensitive_property("sonar.webhook.token"));
+        assert!(!is_sensitive_property("sonar.core.serverBaseURL"));
+        assert!(!is_sensitive_property("sonar.tokenValidity"));
+    }
+
+    #[test]
+    fn deserialized_values_are_secrets() {
+        let secret: SecretString = serde_yaml::from_str("s3cr3t").unwrap();
+
+        assert!(secret.is_secret());
+        assert_eq!(format!("{:?}", secret), "******");
+        assert_eq!(serde_yaml::to_string(&secret).unwrap().trim_start_matches("---").trim(), "s3cr3t");
+    }
+
+    #[test]
+    fn mapping_keeps_the_secret() {
+        let secret = SecretString::secret("${PASSWORD}".to_string()).map(&|_| "s3cr3t".to_string());
+
+        assert!(secret.is_secret());
+        assert_eq!(secret.expose(), "s3cr3t");
+    }
+}
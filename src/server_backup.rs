@@ -0,0 +1,310 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use config_file_model::{ConfigurationFile, CustomRule, Group, Notification, PasswordPolicy, Property, QualityProfile, User, UserState};
+use reconcile::{reconcile, ReconcileOptions};
+use report::Report;
+use secret_string::SecretString;
+use sonar_api::{SonarApiError, ADMIN_USER, DEFAULT_GROUP};
+use sonar_api_model::{SonarRuleParam, SonarUser};
+use sonar_client::SonarClient;
+
+// Configuration file of a backup, restored like any configuration file.
+pub const CONFIGURATION_FILE: &str = "configuration.yaml";
+
+// Directory of a backup containing the XML backup of every quality profile.
+pub const QUALITY_PROFILES_DIRECTORY: &str = "quality-profiles";
+
+// Configuration reproducing the state of SonarQube: properties, groups with their permissions, users with their groups
+// and notifications, custom rules. Secured properties, properties with several values or fields and passwords cannot
+// be set back, they are not part of it: the password of a local user is a required variable. Other values are escaped,
+// they are restored as is whatever they contain.
+pub fn export_configuration(client: &dyn SonarClient) -> Result<ConfigurationFile, SonarApiError> {
+    let multi_valued_properties = client.get_multi_valued_properties()?;
+    for name in &multi_valued_properties {
+        warn!("The property [{}] has several values or fields, it's not backed up.", name);
+    }
+
+    let properties = client.get_property_values()?.into_iter()
+        .filter(|(name, _)| !multi_valued_properties.contains(name))
+        .map(|(name, value)| Property { name: escape(&name), value: escape(&value), sensitive: None })
+        .collect();
+
+    let template_groups = client.get_template_groups()?;
+    let groups = client.get_groups()?.into_iter()
+        .map(|group| Group {
+            permissions: template_groups.iter().filter(|template_group| template_group.name == group.name).flat_map(|template_group| template_group.permissions.clone()).collect(),
+            name: escape(&group.name),
+            description: escape(&group.description),
+            members: None,
+        })
+        .collect();
+
+    let mut users = Vec::new();
+    for user in client.get_users()? {
+        // groups and password of the administrator are not managed
+        if user.login == ADMIN_USER {
+            continue;
+        }
+
+        let notifications = if user.is_active() { Some(client.get_user_notifications(&user.login)?) } else { None };
+
+        users.push(export_user(user, notifications.map(|notifications| notifications.into_iter()
+            .map(|notification| Notification {
+                notification_type: escape(&notification.notification_type),
+                project: notification.project.as_deref().map(escape),
+                channel: Some(escape(&notification.channel)),
+            })
+            .collect())));
+    }
+
+    let custom_rules = client.get_custom_rules()?.into_iter()
+        .map(|rule| CustomRule {
+            custom_key: escape(rule.key.splitn(2, ':').last().unwrap_or_default()),
+            template_key: escape(rule.template_key.as_deref().unwrap_or_default()),
+            name: escape(&rule.name),
+            severity: rule.severity,
+            rule_type: rule.rule_type,
+            markdown_description: escape(rule.markdown_description.as_deref().unwrap_or_default()),
+            params: Some(rule.params.into_iter().filter_map(|SonarRuleParam { key, default_value }| default_value.map(|value| (key, escape(&value)))).collect()),
+        })
+        .collect();
+
+    return Ok(ConfigurationFile { properties: Some(properties), users: Some(users), groups: Some(groups), custom_rules: Some(custom_rules), quality_profiles: None });
+}
+
+fn export_user(user: SonarUser, notifications: Option<Vec<Notification>>) -> User {
+    let local = user.is_local();
+
+    User {
+        state: if user.is_active() { None } else { Some(UserState::Deactivated) },
+        groups: Some(user.groups.iter().filter(|group| *group != DEFAULT_GROUP).map(|group| escape(group)).collect()),
+        local: Some(local),
+        external_identity: if local { None } else { user.external_identity.as_deref().map(escape) },
+        external_provider: if local { None } else { user.external_provider.as_deref().map(escape) },
+        // the password cannot be read, it's given when the user is created by the restore, the current one is kept
+        password: if local { Some(SecretString::plain(password_variable(&user.login))) } else { None },
+        password_policy: Some(PasswordPolicy::CreateOnly),
+        anonymize: None,
+        email: user.email.as_deref().map(escape),
+        scm_accounts: user.scm_accounts.map(|scm_accounts| scm_accounts.iter().map(|scm_account| escape(scm_account)).collect()),
+        notifications,
+        login: escape(&user.login),
+        name: escape(&user.name),
+    }
+}
+
+// Required variable of the password of a local user, e.g. ${JDOE_PASSWORD:?...} for jdoe.
+fn password_variable(login: &str) -> String {
+    let name: String = login.chars().map(|character| if character.is_ascii_alphanumeric() { character.to_ascii_uppercase() } else { '_' }).collect();
+
+    format!("${{{}_PASSWORD:?password of a local user, it cannot be backed up}}", name)
+}
+
+// Variables of the configuration are resolved when it's restored, values looking like one are kept literal.
+fn escape(value: &str) -> String {
+    value.replace("${", "$${")
+}
+
+// Writes the configuration and the quality profiles of SonarQube in a new directory named after the current time,
+// the directory is returned. Built-in quality profiles cannot be restored, they are not backed up.
+pub fn backup(client: &dyn SonarClient, directory: &Path) -> Result<PathBuf, SonarApiError> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or_default();
+    let target = directory.join(format!("sonar-backup-{}", format_timestamp(seconds)));
+    let profiles_directory = target.join(QUALITY_PROFILES_DIRECTORY);
+
+    fs::create_dir_all(&profiles_directory).map_err(|error| write_error(&profiles_directory, error))?;
+
+    let mut configuration = export_configuration(client)?;
+    let mut quality_profiles = Vec::new();
+    for profile in client.get_quality_profiles()?.into_iter().filter(|profile| !profile.is_built_in) {
+        let file = format!("{}/{}-{}.xml", QUALITY_PROFILES_DIRECTORY, profile.language, file_name(&profile.name));
+
+        write_file(&target.join(&file), &client.backup_quality_profile(&profile.language, &profile.name)?)?;
+        quality_profiles.push(QualityProfile { file });
+    }
+    configuration.quality_profiles = Some(quality_profiles);
+
+    let configuration = serde_yaml::to_string(&configuration)
+        .map_err(|error| SonarApiError::new(format!("Cannot serialize the configuration: {}", error)))?;
+    write_file(&target.join(CONFIGURATION_FILE), &configuration)?;
+
+    return Ok(target);
+}
+
+// Applies the configuration of the backup like the setup does, its quality profiles are restored when they differ. The
+// outcome of every resource is recorded in the report.
+pub fn restore(client: &dyn SonarClient, directory: &Path, options: &ReconcileOptions, report: &mut Report) -> Result<(), SonarApiError> {
+    let configuration_file = directory.join(CONFIGURATION_FILE);
+    let mut configuration: ConfigurationFile = serde_yaml::from_str(&read_file(&configuration_file)?)
+        .map_err(|error| SonarApiError::new(format!("Cannot parse the configuration [{}]: {}", configuration_file.display(), error)))?;
+
+    // files of the quality profiles are relative to the backup
+    for profile in configuration.quality_profiles.iter_mut().flatten() {
+        profile.file = directory.join(&profile.file).to_string_lossy().to_string();
+    }
+
+    return reconcile(configuration, client, options, report);
+}
+
+fn read_file(file: &Path) -> Result<String, SonarApiError> {
+    return fs::read_to_string(file).map_err(|error| SonarApiError::new(format!("Cannot read the file [{}]: {}", file.display(), error)));
+}
+
+fn write_file(file: &Path, content: &str) -> Result<(), SonarApiError> {
+    return fs::write(file, content).map_err(|error| write_error(file, error));
+}
+
+fn write_error(file: &Path, error: std::io::Error) -> SonarApiError {
+    SonarApiError::new(format!("Cannot write the backup [{}]: {}", file.display(), error))
+}
+
+// Name usable as a file name on every platform.
+fn file_name(name: &str) -> String {
+    name.chars().map(|character| if character.is_ascii_alphanumeric() || character == '-' { character } else { '_' }).collect()
+}
+
+// UTC date and time of the timestamp (in seconds), e.g. 20200315-143000.
+fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+
+    // civil date of the number of days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, time / 3_600, time % 3_600 / 60, time % 60);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use serde_yaml;
+
+    use super::{backup, export_configuration, format_timestamp, restore};
+    use in_memory_sonar::InMemorySonar;
+    use reconcile::{reconcile, ReconcileOptions};
+    use report::Report;
+    use sonar_client::SonarClient;
+
+    const CONFIGURATION: &str = r#"
+properties:
+  - name: sonar.core.serverBaseURL
+    value: https://sonar.example.com
+groups:
+  - name: developers
+    description: Developers
+    permissions: [codeviewer, scan]
+users:
+  - login: jdoe
+    name: John Doe
+    local: false
+    externalProvider: saml
+    email: jdoe@example.com
+    groups: [developers]
+    notifications:
+      - type: ChangesOnMyIssue
+customRules:
+  - templateKey: java:S124
+    customKey: no-todo
+    name: No TODO
+    markdownDescription: TODO comments must be tracked as issues
+    params:
+      regularExpression: .*TODO.*
+"#;
+
+    const PROFILE: &str = "<?xml version='1.0' encoding='UTF-8'?><profile><name>Strict</name><language>java</language><rules/></profile>";
+
+    #[test]
+    fn formats_timestamps_in_utc() {
+        assert_eq!(format_timestamp(0), "19700101-000000");
+        assert_eq!(format_timestamp(1_584_282_600), "20200315-143000");
+        assert_eq!(format_timestamp(1_709_210_096), "20240229-123456");
+    }
+
+    #[test]
+    fn restores_a_backup_on_another_instance() {
+        let source = InMemorySonar::new();
+        reconcile(serde_yaml::from_str(CONFIGURATION).unwrap(), &source, &ReconcileOptions::default(), &mut Report::new("setup")).unwrap();
+        source.restore_quality_profile(PROFILE).unwrap();
+
+        let directory = env::temp_dir().join(format!("sonar-as-code-backup-{}", std::process::id()));
+        let backup_directory = backup(&source, &directory).unwrap();
+
+        let target = InMemorySonar::new();
+        restore(&target, &backup_directory, &ReconcileOptions::default(), &mut Report::new("restore")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(target.get_property_value("sonar.core.serverBaseURL").unwrap().as_deref(), Some("https://sonar.example.com"));
+        assert!(target.get_template_groups().unwrap().iter().any(|group| group.name == "developers" && group.permissions == vec!["codeviewer", "scan"]));
+        assert_eq!(target.get_user_groups("jdoe").unwrap(), vec!["developers", "sonar-users"]);
+        assert_eq!(target.get_user_notifications("jdoe").unwrap().len(), 1);
+        assert_eq!(target.backup_quality_profile("java", "Strict").unwrap(), PROFILE);
+        assert_eq!(target.get_rule("java:no-todo").unwrap().unwrap().param("regularExpression"), Some(".*TODO.*"));
+    }
+
+    #[test]
+    fn restores_values_looking_like_variables_as_is() {
+        let source = InMemorySonar::new();
+        reconcile(serde_yaml::from_str(&CONFIGURATION.replace("https://sonar.example.com", "$${HOME}/sonar").replace("description: Developers", "description: $$${USER}")).unwrap(),
+                  &source, &ReconcileOptions::default(), &mut Report::new("setup")).unwrap();
+        assert_eq!(source.get_property_value("sonar.core.serverBaseURL").unwrap().as_deref(), Some("${HOME}/sonar"));
+
+        let directory = env::temp_dir().join(format!("sonar-as-code-backup-variables-{}", std::process::id()));
+        let backup_directory = backup(&source, &directory).unwrap();
+
+        let target = InMemorySonar::new();
+        restore(&target, &backup_directory, &ReconcileOptions::default(), &mut Report::new("restore")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(target.get_property_value("sonar.core.serverBaseURL").unwrap().as_deref(), Some("${HOME}/sonar"));
+        assert_eq!(target.get_group_by_name("developers").unwrap().unwrap().description, "$${USER}");
+    }
+
+    #[test]
+    fn restores_local_users_with_the_password_of_a_variable() {
+        let source = InMemorySonar::new();
+        reconcile(serde_yaml::from_str(&CONFIGURATION.replace("users:", "users:\n  - login: ci.bot\n    name: CI Bot\n    password: s3cr3t")).unwrap(),
+                  &source, &ReconcileOptions::default(), &mut Report::new("setup")).unwrap();
+
+        let directory = env::temp_dir().join(format!("sonar-as-code-backup-local-users-{}", std::process::id()));
+        let backup_directory = backup(&source, &directory).unwrap();
+        let configuration = fs::read_to_string(backup_directory.join(super::CONFIGURATION_FILE)).unwrap();
+
+        // the password is required to create the user
+        let target = InMemorySonar::new();
+        let error = restore(&target, &backup_directory, &ReconcileOptions::default(), &mut Report::new("restore")).unwrap_err();
+        assert!(error.to_string().contains("Variable [CI_BOT_PASSWORD] is required"), "{}", error);
+
+        env::set_var("CI_BOT_PASSWORD", "n3w-s3cr3t");
+        let target = InMemorySonar::new();
+        restore(&target, &backup_directory, &ReconcileOptions::default(), &mut Report::new("restore")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(configuration.contains("password: \"${CI_BOT_PASSWORD:?password of a local user, it cannot be backed up}\""), "{}", configuration);
+        assert!(target.get_user("ci.bot").unwrap().unwrap().is_local());
+        assert!(target.get_user("jdoe").unwrap().is_some());
+    }
+
+    #[test]
+    fn does_not_export_properties_with_several_values() {
+        let sonar = InMemorySonar::new();
+        sonar.set_multi_valued_property("sonar.global.exclusions", &["**/generated/**", "**/vendor/**"]);
+        reconcile(serde_yaml::from_str(CONFIGURATION).unwrap(), &sonar, &ReconcileOptions::default(), &mut Report::new("setup")).unwrap();
+
+        let configuration = export_configuration(&sonar).unwrap();
+
+        let properties: Vec<String> = configuration.properties.unwrap().into_iter().map(|property| property.name).collect();
+        assert_eq!(properties, vec!["sonar.core.serverBaseURL"]);
+    }
+}
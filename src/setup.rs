@@ -1,16 +1,17 @@
-use std::fs::File;
-use std::io::Read;
-
 use structopt::StructOpt;
 
-use config_file_model::ConfigurationFile;
-use sonar_api::SonarApi;
-use sonar_api_model::{SonarGroupCreationRequest, SonarProperty, SonarUser};
+use configuration_options::ConfigurationOptions;
+use report_options::ReportOptions;
+use sonar_as_code::config_file_model::ConfigurationFile;
+use sonar_as_code::reconcile::{reconcile, ReconcileOptions};
+use sonar_as_code::report::Report;
+use sonar_as_code::secret_string::SecretString;
+use sonar_as_code::sonar_api::{SonarApi, SonarApiError};
 
 #[derive(StructOpt, Debug)]
 pub struct SetupCmd {
-    #[structopt(name = "file", short = "f", about = "file to the YAML configuration file")]
-    file: String,
+    #[structopt(flatten)]
+    configuration: ConfigurationOptions,
 
     #[structopt(name = "sonarUrl", short = "s", about = "URL of SonarQube")]
     url: String,
@@ -19,54 +20,46 @@ pub struct SetupCmd {
     username: String,
 
     #[structopt(name = "password", short = "p", default_value = "admin", about = "Password of the administrator")]
-    password: String,
+    password: SecretString,
 
     #[structopt(name = "nbAttempts", short = "a", default_value = "600", about = "Number of attemps to connect to the API (1sec between attempts)")]
     number_attempts: usize,
-}
-
-    pub fn setup(cmd: SetupCmd) -> Result<(), reqwest::Error> {
-    let config_file: ConfigurationFile = load_configuration(&cmd);
 
-    let mut sonar_api = SonarApi::new(cmd.url.to_string(), cmd.username.to_string(), cmd.password.to_string(), cmd.number_attempts);
+    #[structopt(name = "keepGoing", long = "keep-going", about = "apply every resource that can be applied, failures are summarized at the end (fail-fast by default)")]
+    keep_going: bool,
 
-    sonar_api.wait_ready();
-
-    for property in SonarProperty::from_configuration_properties(&config_file.properties.unwrap_or(Vec::new()), resolve_variables) {
-        sonar_api.set_property(&property);
-    }
+    #[structopt(name = "prune", long = "prune", about = "delete resources that are not declared (groups), built-in groups are kept")]
+    prune: bool,
 
-    for group in SonarGroupCreationRequest::from_configuration_groups(&config_file.groups.unwrap_or(Vec::new()), resolve_variables) {
-        sonar_api.create_group(&group);
-    }
+    #[structopt(name = "parallelism", long = "parallelism", default_value = "1", about = "number of resources applied concurrently, a resource is applied after the ones it depends on")]
+    parallelism: usize,
 
-    for user in SonarUser::from_configuration_users(&config_file.users.unwrap_or(Vec::new()), resolve_variables) {
-        sonar_api.create_user(&user);
-    }
+    #[structopt(name = "rateLimit", long = "rate-limit", about = "maximum number of requests sent per second to SonarQube (unlimited by default)")]
+    rate_limit: Option<u32>,
 
-    Ok(())
+    #[structopt(flatten)]
+    report: ReportOptions,
 }
 
-fn load_configuration(setup: &SetupCmd) -> ConfigurationFile {
-    debug!("Run setup command with configuration file {:?}", setup.file);
+pub fn setup(cmd: SetupCmd) -> Result<(), SonarApiError> {
+    let config_file: ConfigurationFile = cmd.configuration.load().map_err(SonarApiError::new)?;
+    let options = ReconcileOptions { keep_going: cmd.keep_going, env_file: cmd.configuration.env_file.clone(), prune: cmd.prune, parallelism: cmd.parallelism };
 
-    let mut file = File::open(&setup.file)
-        .expect("Cannot open configuration file.");
+    let sonar_api = SonarApi::new(cmd.url.to_string(), cmd.username.to_string(), cmd.password.clone(), cmd.number_attempts)
+        .with_rate_limit(cmd.rate_limit);
 
-    let mut file_content = String::new();
-    file.read_to_string(&mut file_content)
-        .expect("Cannot read configuration file.");
+    sonar_api.wait_ready()?;
 
-    return serde_yaml::from_str(&file_content)
-        .expect("Error while loading configuration file.");
-}
+    let mut report = Report::new("setup");
+    let result = reconcile(config_file, &sonar_api, &options, &mut report);
 
-fn resolve_variables(tokenized: &String) -> String {
-    let context = std::env::vars().collect();
+    cmd.report.publish(&report).map_err(SonarApiError::new)?;
 
-    let resolve_string = envsubst::substitute(tokenized, &context).unwrap();
-
-    assert!(!envsubst::is_templated(&resolve_string), format!("Cannot resolve all variables from [{}].", tokenized));
+    if cmd.keep_going {
+        for failure in report.failures() {
+            eprintln!("Failed {}/{}: {}.", failure.kind, failure.name, failure.error.as_deref().unwrap_or_default());
+        }
+    }
 
-    return resolve_string;
+    return result;
 }
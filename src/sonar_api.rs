@@ -1,292 +1,912 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
 use retry::delay::Fixed;
 use retry::retry;
 use serde::de::DeserializeOwned;
 
-use sonar_api_model::{SonarErrors, SonarGeneratedToken, SonarGroup, SonarGroupCreationRequest, SonarGroups, SonarPermissionTemplates, SonarProperty, SonarUser, SonarUserGroups, SonarUsers};
+use rate_limiter::RateLimiter;
+use secret_string::SecretString;
+use sonar_api_model::{SonarCustomRule, SonarErrors, SonarGeneratedToken, SonarGroup, SonarGroupCreationRequest, SonarGroupMembers, SonarGroups, SonarNotification, SonarNotifications, SonarPaging, SonarProperty, SonarQualityProfile, SonarQualityProfiles, SonarRule, SonarRuleDetails, SonarRules, SonarSetting, SonarSettings, SonarTemplateGroup, SonarTemplateGroups, SonarUser, SonarUserGroups, SonarUserUpdate, SonarUsers};
+use sonar_client::SonarClient;
 
 pub struct SonarApi {
     url: String,
     username: String,
-    password: String,
+    // changed when the password of the administrator is changed
+    password: RwLock<SecretString>,
     number_attempts: usize,
+    rate_limiter: Option<RateLimiter>,
+}
+
+pub const DEFAULT_TEMPLATE_NAME: &str = "default_template";
+pub const DEFAULT_GROUP: &str = "sonar-users";
+pub const ADMIN_GROUP: &str = "sonar-administrators";
+pub const ADMIN_USER: &str = "admin";
+const PAGE_SIZE: &str = "100";
+
+// Requests rejected because too many were sent (HTTP 429) are sent again after the delay asked by SonarQube (or its
+// proxy), a few times at most.
+const MAX_THROTTLED_ATTEMPTS: usize = 5;
+const DEFAULT_RETRY_AFTER_SECONDS: u64 = 1;
+const MAX_RETRY_AFTER_SECONDS: u64 = 60;
+
+// Groups existing on every SonarQube, they don't need to be declared.
+pub fn is_built_in_group(name: &str) -> bool {
+    return name == DEFAULT_GROUP || name == ADMIN_GROUP;
 }
 
-const DEFAULT_TEMPLATE_NAME: &str = "default_template";
-const DEFAULT_GROUP: &str = "sonar-users";
-const ADMIN_USER: &str = "admin";
+// Permissions that can be granted to a group in a permission template.
+pub const TEMPLATE_PERMISSIONS: [&str; 6] = ["admin", "codeviewer", "issueadmin", "securityhotspotadmin", "scan", "user"];
+
+pub const RULE_SEVERITIES: [&str; 5] = ["INFO", "MINOR", "MAJOR", "CRITICAL", "BLOCKER"];
+
+pub const RULE_TYPES: [&str; 4] = ["CODE_SMELL", "BUG", "VULNERABILITY", "SECURITY_HOTSPOT"];
 
 
 // See [URL]/web_api/
 impl SonarApi {
     pub fn new(url: String,
                username: String,
-               password: String,
+               password: SecretString,
                number_attempts: usize) -> SonarApi {
-        SonarApi { url: if url.ends_with("/") { url.chars().take(url.len() - 1).collect() } else { url }, username, password, number_attempts }
+        SonarApi { url: if url.ends_with("/") { url.chars().take(url.len() - 1).collect() } else { url }, username, password: RwLock::new(password), number_attempts, rate_limiter: None }
+    }
+
+    // Limits the number of requests sent per second, requests sent concurrently included.
+    pub fn with_rate_limit(mut self, requests_per_second: Option<u32>) -> SonarApi {
+        self.rate_limiter = requests_per_second.map(RateLimiter::new);
+
+        return self;
     }
 
-    pub fn wait_ready(&self) {
+    pub fn wait_ready(&self) -> Result<(), SonarApiError> {
         debug!("Checking if SonarQube is available on URL [{}].", &self.url);
 
         let mut attempt = 0;
         let result = retry(Fixed::from_millis(1000).take(self.number_attempts), || {
-            attempt = attempt + 1;
+            attempt += 1;
             debug!("Attempt number to connect to the API {}.", attempt);
 
-            reqwest::blocking::Client::new()
-                .get(&[&self.url, "/api"].concat())
-                .basic_auth(&self.username, Some(&self.password))
-                .send()
+            self.execute_get("/api", &[])
             // TODO: check not 401 in wait_ready
         });
 
-        assert!(result.is_ok(), "Error while trying to connect to the API.");
+        if result.is_err() {
+            return Err(SonarApiError::new("Error while trying to connect to the API".to_string()));
+        }
+
+        return Ok(());
     }
 
-    pub fn set_property(&self, property: &SonarProperty) {
-        debug!("Setting property [{}] = [{}].", property.name, property.value);
+    pub fn generate_user_token(&self, user: &str, name: &str) -> Result<SecretString, SonarApiError> {
+        let resp = self.execute_post("/api/user_tokens/generate", &[("login", user), ("name", name)])?;
 
-        let resp = self.execute_post(
-            self.build_url("/api/settings/set", &vec![("key", property.name.as_str()), ("value", property.value.as_str())]).as_str()
-        );
+        return Ok(SonarApi::deserialize_response::<SonarGeneratedToken>(resp, format!("Error while generating user token [{}]", name))?.token);
+    }
+
+    // The parameter is repeated for every account, an empty account removes all of them.
+    fn push_scm_accounts<'a>(params: &mut Vec<(&str, &'a str)>, scm_accounts: &'a [String]) {
+        if scm_accounts.is_empty() {
+            params.push(("scmAccount", ""));
+        }
+
+        for scm_account in scm_accounts {
+            params.push(("scmAccount", scm_account.as_str()));
+        }
+    }
 
-        SonarApi::assert_response(resp, format!("Error while setting property [{}].", property.name));
+    // Parameters of a rule are sent as key=value pairs separated by semicolons, values containing one are quoted.
+    fn rule_params(params: &BTreeMap<String, String>) -> String {
+        params.iter()
+            .map(|(key, value)| if value.contains(';') { format!("{}=\"{}\"", key, value) } else { format!("{}={}", key, value) })
+            .collect::<Vec<String>>()
+            .join(";")
     }
 
-    pub fn create_group(&self, group: &SonarGroupCreationRequest) {
-        debug!("Creating group [{}].", group.name);
+    fn custom_rule_params<'a>(rule: &'a SonarCustomRule, params: &'a str) -> Vec<(&'a str, &'a str)> {
+        let mut request_params = vec![("name", rule.name.as_str()), ("markdown_description", rule.markdown_description.as_str())];
+
+        if let Some(severity) = &rule.severity {
+            request_params.push(("severity", severity.as_str()));
+        }
+
+        if !params.is_empty() {
+            request_params.push(("params", params));
+        }
+
+        return request_params;
+    }
+
+    fn notification_params<'a>(login: &'a str, notification: &'a SonarNotification) -> Vec<(&'a str, &'a str)> {
+        let mut params = vec![("login", login), ("type", notification.notification_type.as_str()), ("channel", notification.channel.as_str())];
+
+        if let Some(project) = &notification.project {
+            params.push(("project", project.as_str()));
+        }
 
-        if !self.group_exists(group.name.as_str()) {
-            let resp = self.execute_post(
-                self.build_url("/api/user_groups/create", &vec![("name", group.name.as_str()), ("description", group.description.as_str())]).as_str()
-            );
+        return params;
+    }
+
+    fn current_password(&self) -> SecretString {
+        return self.password.read().expect("Cannot read the password of the administrator.").clone();
+    }
 
-            SonarApi::assert_response(resp, format!("Error while creating group [{}].", group.name));
+    fn execute_get(&self, path: &str, params: &[(&str, &str)]) -> Result<reqwest::blocking::Response, SonarApiError> {
+        return self.execute(reqwest::Method::GET, path, params);
+    }
+
+    fn execute_post(&self, path: &str, params: &[(&str, &str)]) -> Result<reqwest::blocking::Response, SonarApiError> {
+        return self.execute(reqwest::Method::POST, path, params);
+    }
+
+    // Every request is built here. Parameters of a GET are sent in the query string, parameters of a POST are sent as
+    // a form-encoded body: they may contain secrets that must not end up in logs of proxies.
+    fn execute(&self, method: reqwest::Method, path: &str, params: &[(&str, &str)]) -> Result<reqwest::blocking::Response, SonarApiError> {
+        let url = format!("{}{}", self.url, path);
+        let client = reqwest::blocking::Client::new();
+
+        let request = if method == reqwest::Method::GET {
+            let url = reqwest::Url::parse_with_params(&url, params)
+                .map_err(|error| SonarApiError::new(format!("Invalid URL [{}]: {}", url, error)))?;
+
+            client.get(url)
         } else {
-            let group_id = self.get_group_by_name(group.name.as_str()).expect("Expecting a group.").id.to_string();
-            let resp =
-                self.execute_post(
-                    self.build_url(
-                        "/api/user_groups/update",
-                        &vec![("id", group_id.as_str()), ("description", group.description.as_str())],
-                    ).as_str()
-                );
-
-            SonarApi::assert_response(resp, format!("Error while updating group [{}].", group.name));
+            client.request(method, &url).form(params)
+        };
+
+        return self.send(request);
+    }
+
+    // Files are uploaded as a multipart body.
+    fn execute_multipart(&self, path: &str, form: reqwest::blocking::multipart::Form) -> Result<reqwest::blocking::Response, SonarApiError> {
+        return self.send(reqwest::blocking::Client::new().post(&format!("{}{}", self.url, path)).multipart(form));
+    }
+
+    // Sends the request authenticated as the administrator, the rate limit is respected. Throttled requests are sent
+    // again when their body can be cloned (it cannot for uploaded files).
+    fn send(&self, request: reqwest::blocking::RequestBuilder) -> Result<reqwest::blocking::Response, SonarApiError> {
+        let mut request = request.basic_auth(&self.username, Some(self.current_password().expose()));
+        let mut attempt = 1;
+
+        loop {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire();
+            }
+
+            let retry = request.try_clone().filter(|_| attempt < MAX_THROTTLED_ATTEMPTS);
+
+            let resp = request.send()
+                .map_err(|error| SonarApiError::new(format!("Error while connecting to SonarQube: {}", SonarApi::describe_error(&error))))?;
+
+            match retry {
+                Some(retry) if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    let delay = SonarApi::retry_after(&resp);
+                    debug!("Too many requests sent to SonarQube, sending the request again in {} second(s).", delay.as_secs());
+
+                    thread::sleep(delay);
+                    request = retry;
+                    attempt += 1;
+                }
+                _ => return Ok(resp),
+            }
         }
+    }
+
+    // Delay in seconds asked by the Retry-After header, HTTP dates are not supported.
+    fn retry_after(resp: &reqwest::blocking::Response) -> Duration {
+        let seconds = resp.headers().get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_RETRY_AFTER_SECONDS);
+
+        return Duration::from_secs(seconds.min(MAX_RETRY_AFTER_SECONDS));
+    }
+
+    // The description of reqwest errors contains the URL, its query may contain secrets.
+    fn describe_error(error: &reqwest::Error) -> String {
+        let description = error.to_string();
 
-        let templates = self.get_permission_templates();
-        for permission in templates.permissions {
-            if group.permissions.contains(&permission.key) {
-                self.add_permission_to_group(&group.name, &permission.key);
-            } else {
-                self.remove_permission_to_group(&group.name, &permission.key);
+        match error.url() {
+            Some(url) if url.query().is_some() => {
+                let mut masked_url = url.clone();
+                masked_url.set_query(Some("******"));
+
+                description.replace(url.as_str(), masked_url.as_str())
             }
+            _ => description,
         }
     }
 
-    pub fn add_permission_to_group(&self, group: &String, permission: &String) {
-        debug!("Assign permission [{}] to group [{}].", permission, group);
+    // Fetches every page of a paginated search, the items of a page are extracted with the specified function.
+    fn get_all_pages<P: DeserializeOwned, I>(&self,
+                                            path: &str,
+                                            params: &[(&str, &str)],
+                                            extract: fn(P) -> (SonarPaging, Vec<I>),
+                                            msg: String) -> Result<Vec<I>, SonarApiError> {
+        let mut items = Vec::new();
+        let mut page_index = 1;
+
+        loop {
+            let page_index_value = page_index.to_string();
+            let mut page_params = params.to_vec();
+            page_params.push(("p", page_index_value.as_str()));
+            page_params.push(("ps", PAGE_SIZE));
+
+            let resp = self.execute_get(path, &page_params)?;
+            let (paging, page_items) = extract(SonarApi::deserialize_response::<P>(resp, msg.to_string())?);
+
+            let last_page = page_items.is_empty() || paging.page_index * paging.page_size >= paging.total;
+            items.extend(page_items);
+
+            if last_page {
+                return Ok(items);
+            }
+
+            page_index += 1;
+        }
+    }
+
+    fn check_response(resp: reqwest::blocking::Response, msg: String) -> Result<(), SonarApiError> {
+        if !resp.status().is_success() {
+            return Err(SonarApiError::from_response(msg, resp));
+        }
 
-        let resp = self.execute_post(
-            self.build_url(
-                "/api/permissions/add_group_to_template",
-                &vec![("groupName", group.as_str()), ("permission", permission.as_str()), ("templateId", DEFAULT_TEMPLATE_NAME)],
-            ).as_str()
+        return Ok(());
+    }
+
+    fn deserialize_response<T: DeserializeOwned>(resp: reqwest::blocking::Response, msg: String) -> Result<T, SonarApiError> {
+        if !resp.status().is_success() {
+            return Err(SonarApiError::from_response(msg, resp));
+        }
+
+        return resp.json::<T>()
+            .map_err(|error| SonarApiError::new(format!("{}: {}", msg, SonarApi::describe_error(&error))));
+    }
+}
+
+impl SonarClient for SonarApi {
+    fn get_property_value(&self, key: &str) -> Result<Option<String>, SonarApiError> {
+        let resp = self.execute_get("/api/settings/values", &[("keys", key)])?;
+
+        let settings: SonarSettings =
+            SonarApi::deserialize_response::<SonarSettings>(resp, format!("Cannot deserialize response retrieving property [{}]", key))?;
+
+        return Ok(
+            settings.settings.into_iter()
+                .find(|setting| setting.key == key && !setting.inherited.unwrap_or(false))
+                .and_then(SonarSetting::into_value)
         );
+    }
+
+    fn get_property_values(&self) -> Result<BTreeMap<String, String>, SonarApiError> {
+        let resp = self.execute_get("/api/settings/values", &[])?;
 
-        SonarApi::assert_response(resp, format!("Error while creating permission [{}].", permission));
+        let settings: SonarSettings =
+            SonarApi::deserialize_response::<SonarSettings>(resp, "Cannot deserialize response retrieving properties".to_string())?;
+
+        return Ok(
+            settings.settings.into_iter()
+                .filter(|setting| !setting.inherited.unwrap_or(false))
+                .filter_map(|setting| {
+                    let key = setting.key.to_string();
+
+                    setting.into_value().map(|value| (key, value))
+                })
+                .collect()
+        );
     }
 
-    pub fn remove_permission_to_group(&self, group: &String, permission: &String) {
-        debug!("Remove permission [{}] to group [{}].", permission, group);
+    fn get_multi_valued_properties(&self) -> Result<BTreeSet<String>, SonarApiError> {
+        let resp = self.execute_get("/api/settings/values", &[])?;
 
-        let resp = self.execute_post(
-            self.build_url(
-                "/api/permissions/remove_group_from_template",
-                &vec![("groupName", group), ("permission", permission), ("templateId", DEFAULT_TEMPLATE_NAME)],
-            ).as_str()
+        let settings: SonarSettings =
+            SonarApi::deserialize_response::<SonarSettings>(resp, "Cannot deserialize response retrieving properties".to_string())?;
+
+        return Ok(
+            settings.settings.into_iter()
+                .filter(|setting| !setting.inherited.unwrap_or(false) && setting.is_multi_valued())
+                .map(|setting| setting.key)
+                .collect()
         );
+    }
+
+    fn set_property(&self, property: &SonarProperty) -> Result<(), SonarApiError> {
+        debug!("Setting property [{}] = [{}].", property.name, property.value);
 
-        SonarApi::assert_response(resp, format!("Error while removing permission [{}].", permission));
+        let resp = self.execute_post("/api/settings/set", &[("key", property.name.as_str()), ("value", property.value.expose())])?;
+
+        return SonarApi::check_response(resp, format!("Error while setting property [{}]", property.name));
     }
 
-    pub fn group_exists(&self, _name: &str) -> bool {
-        return self.get_group_by_name(_name).is_some();
+    fn get_group_by_name(&self, _name: &str) -> Result<Option<SonarGroup>, SonarApiError> {
+        let resp = self.execute_get("/api/user_groups/search", &[("q", _name)])?;
+
+        let groups: SonarGroups = SonarApi::deserialize_response::<SonarGroups>(resp, format!("Cannot deserialize response retrieving group [{}]", _name))?;
+
+        return Ok(groups.groups.into_iter().find(|group| group.name == _name));
     }
 
-    pub fn create_user(&mut self, user: &SonarUser) {
-        debug!("Creating user [{}].", user.login);
+    fn get_groups(&self) -> Result<Vec<SonarGroup>, SonarApiError> {
+        return self.get_all_pages(
+            "/api/user_groups/search",
+            &[],
+            |page: SonarGroups| (page.paging, page.groups),
+            "Cannot deserialize response retrieving groups".to_string(),
+        );
+    }
 
-        if !self.user_exists(user.login.as_str()) {
-            let password = user.password.clone().unwrap_or("password".to_string());
+    fn create_group(&self, group: &SonarGroupCreationRequest) -> Result<(), SonarApiError> {
+        debug!("Creating group [{}].", group.name);
 
-            let resp =
-                self.execute_post(format!("/api/users/create?login={}&name={}&password={}", user.login, user.name, password).as_str());
+        let resp = self.execute_post("/api/user_groups/create", &[("name", group.name.as_str()), ("description", group.description.as_str())])?;
 
-            SonarApi::assert_response(resp, format!("Error while creating user [{}].", user.login));
-        } else {
-            let resp = self.execute_post(
-                self.build_url("/api/users/update", &vec![("login", user.login.as_str()), ("name", user.name.as_str())]).as_str()
-            );
+        return SonarApi::check_response(resp, format!("Error while creating group [{}]", group.name));
+    }
+
+    fn update_group_description(&self, current_group: &SonarGroup, description: &str) -> Result<(), SonarApiError> {
+        debug!("Updating description of group [{}].", current_group.name);
+
+        let group_id = current_group.id.to_string();
+        let resp = self.execute_post("/api/user_groups/update", &[("id", group_id.as_str()), ("description", description)])?;
 
-            SonarApi::assert_response(resp, format!("Error while updating user [{}].", user.login));
+        return SonarApi::check_response(resp, format!("Error while updating group [{}]", current_group.name));
+    }
+
+    fn delete_group(&self, name: &str) -> Result<(), SonarApiError> {
+        debug!("Deleting group [{}].", name);
+
+        let resp = self.execute_post("/api/user_groups/delete", &[("name", name)])?;
+
+        return SonarApi::check_response(resp, format!("Error while deleting group [{}]", name));
+    }
+
+    fn get_template_groups(&self) -> Result<Vec<SonarTemplateGroup>, SonarApiError> {
+        return self.get_all_pages(
+            "/api/permissions/template_groups",
+            &[("templateId", DEFAULT_TEMPLATE_NAME)],
+            |page: SonarTemplateGroups| (page.paging, page.groups),
+            "Cannot deserialize groups of the permission template".to_string(),
+        );
+    }
+
+    fn add_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError> {
+        debug!("Assign permission [{}] to group [{}].", permission, group);
+
+        let resp = self.execute_post("/api/permissions/add_group_to_template", &[("groupName", group), ("permission", permission), ("templateId", DEFAULT_TEMPLATE_NAME)])?;
+
+        return SonarApi::check_response(resp, format!("Error while creating permission [{}]", permission));
+    }
+
+    fn remove_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError> {
+        debug!("Remove permission [{}] to group [{}].", permission, group);
+
+        let resp = self.execute_post("/api/permissions/remove_group_from_template", &[("groupName", group), ("permission", permission), ("templateId", DEFAULT_TEMPLATE_NAME)])?;
 
-            if user.password.is_some() {
-                self.change_user_password(&user.login, user.password.as_ref().unwrap());
+        return SonarApi::check_response(resp, format!("Error while removing permission [{}]", permission));
+    }
+
+    // The search matches logins partially, every page is read to find the exact login, active or deactivated.
+    fn get_user(&self, _login: &str) -> Result<Option<SonarUser>, SonarApiError> {
+        for deactivated in &["false", "true"] {
+            let users = self.get_all_pages(
+                "/api/users/search",
+                &[("q", _login), ("deactivated", deactivated)],
+                |page: SonarUsers| (page.paging, page.users),
+                format!("Cannot deserialize response checking if login [{}] exists", _login),
+            )?;
+
+            if let Some(user) = users.into_iter().find(|user| user.login == _login) {
+                return Ok(Some(user));
             }
         }
 
-        if user.login.eq(ADMIN_USER) && !user.groups.is_empty() {
-            panic!("Cannot specify groups of user admin");
+        return Ok(None);
+    }
+
+    fn get_users(&self) -> Result<Vec<SonarUser>, SonarApiError> {
+        let mut users = Vec::new();
+
+        for deactivated in &["false", "true"] {
+            users.extend(self.get_all_pages(
+                "/api/users/search",
+                &[("deactivated", deactivated)],
+                |page: SonarUsers| (page.paging, page.users),
+                "Cannot deserialize response retrieving users".to_string(),
+            )?);
         }
 
-        let current_user_groups = self.get_user_groups(&user.login.to_string());
+        return Ok(users);
+    }
 
-        for current_user_group in &current_user_groups {
-            if user.groups.contains(current_user_group) && current_user_group != DEFAULT_GROUP {
-                self.remove_user_from_group(&user.login, current_user_group);
-            }
+    fn create_user(&self, user: &SonarUser) -> Result<(), SonarApiError> {
+        debug!("Creating user [{}].", user.login);
+
+        let mut params = vec![("login", user.login.as_str()), ("name", user.name.as_str())];
+
+        if let Some(email) = &user.email {
+            params.push(("email", email.as_str()));
         }
 
-        for group in &user.groups {
-            if !current_user_groups.contains(group) {
-                self.add_user_to_group(&user.login, group);
-            }
+        if let Some(scm_accounts) = &user.scm_accounts {
+            SonarApi::push_scm_accounts(&mut params, scm_accounts);
+        }
+
+        if !user.is_local() {
+            params.push(("local", "false"));
+        }
+
+        if let Some(password) = &user.password {
+            params.push(("password", password.expose()));
         }
+
+        let resp = self.execute_post("/api/users/create", &params)?;
+
+        return SonarApi::check_response(resp, format!("Error while creating user [{}]", user.login));
     }
 
-    pub fn user_exists(&self, _login: &str) -> bool {
-        let resp = self.execute_get(self.build_url("/api/users/search", &vec![("q", _login)]).as_str());
+    fn update_user(&self, update: &SonarUserUpdate) -> Result<(), SonarApiError> {
+        debug!("Updating user [{}].", update.login);
 
-        let users: SonarUsers = SonarApi::assert_deserialize_response::<SonarUsers>(resp, format!("Cannot deserialize response checking if login [{}] exists.", _login));
+        let mut params = vec![("login", update.login.as_str())];
 
-        for user in &users.users {
-            if user.login == _login {
-                return true;
-            }
+        if let Some(name) = &update.name {
+            params.push(("name", name.as_str()));
+        }
+
+        if let Some(email) = &update.email {
+            params.push(("email", email.as_str()));
+        }
+
+        if let Some(scm_accounts) = &update.scm_accounts {
+            SonarApi::push_scm_accounts(&mut params, scm_accounts);
+        }
+
+        let resp = self.execute_post("/api/users/update", &params)?;
+
+        return SonarApi::check_response(resp, format!("Error while updating user [{}]", update.login));
+    }
+
+    fn update_identity_provider(&self, user: &SonarUser) -> Result<(), SonarApiError> {
+        debug!("Update identity provider of user [{}].", user.login);
+
+        let provider = user.external_provider.clone().unwrap_or_default();
+
+        let mut params = vec![("login", user.login.as_str()), ("newExternalProvider", provider.as_str())];
+        if let Some(identity) = &user.external_identity {
+            params.push(("newExternalIdentity", identity.as_str()));
         }
 
-        return false;
+        let resp = self.execute_post("/api/users/update_identity_provider", &params)?;
+
+        return SonarApi::check_response(resp, format!("Error while updating the identity provider of user [{}]", user.login));
+    }
+
+    fn change_user_password(&self, user: &str, password: &SecretString) -> Result<(), SonarApiError> {
+        if user == self.username {
+            let previous_password = self.current_password();
+            let resp = self.execute_post("/api/users/change_password", &[("login", user), ("password", password.expose()), ("previousPassword", previous_password.expose())])?;
+
+            SonarApi::check_response(resp, format!("Error while changing user's password [{}]", user))?;
+
+            *self.password.write().expect("Cannot change the password of the administrator.") = password.clone();
+        } else {
+            let resp = self.execute_post("/api/users/change_password", &[("login", user), ("password", password.expose())])?;
+
+            SonarApi::check_response(resp, format!("Error while changing user's password [{}]", user))?;
+        }
+
+        return Ok(());
+    }
+
+    fn deactivate_user(&self, login: &str, anonymize: bool) -> Result<(), SonarApiError> {
+        debug!("Deactivating user [{}].", login);
+
+        let anonymize = anonymize.to_string();
+        let resp = self.execute_post("/api/users/deactivate", &[("login", login), ("anonymize", anonymize.as_str())])?;
+
+        return SonarApi::check_response(resp, format!("Error while deactivating user [{}]", login));
+    }
+
+    fn get_user_groups(&self, _user: &str) -> Result<Vec<String>, SonarApiError> {
+        let groups = self.get_all_pages(
+            "/api/users/groups",
+            &[("login", _user)],
+            |page: SonarUserGroups| (page.paging, page.groups),
+            format!("Cannot deserialize response retrieving groups of user [{}]", _user),
+        )?;
+
+        return Ok(groups.iter().map(|member_ship| member_ship.name.to_string()).collect::<Vec<String>>());
+    }
+
+    fn get_group_members(&self, group: &str) -> Result<Vec<String>, SonarApiError> {
+        let members = self.get_all_pages(
+            "/api/user_groups/users",
+            &[("name", group), ("selected", "selected")],
+            |page: SonarGroupMembers| (SonarPaging { page_index: page.page_index, page_size: page.page_size, total: page.total }, page.users),
+            format!("Cannot deserialize response retrieving members of group [{}]", group),
+        )?;
+
+        return Ok(members.into_iter().filter(|member| member.selected).map(|member| member.login).collect::<Vec<String>>());
     }
 
-    pub fn add_user_to_group(&self, user: &String, group: &String) {
+    fn add_user_to_group(&self, user: &str, group: &str) -> Result<(), SonarApiError> {
         debug!("Add user [{}] to group [{}].", user, group);
 
-        let resp = self.execute_post(
-            self.build_url("/api/user_groups/add_user", &vec![("login", user), ("name", group)]).as_str()
-        );
+        let resp = self.execute_post("/api/user_groups/add_user", &[("login", user), ("name", group)])?;
 
-        SonarApi::assert_response(resp, format!("Error while adding user [{}] to group [{}].", user, group));
+        return SonarApi::check_response(resp, format!("Error while adding user [{}] to group [{}]", user, group));
     }
 
-    pub fn remove_user_from_group(&self, user: &String, group: &String) {
+    fn remove_user_from_group(&self, user: &str, group: &str) -> Result<(), SonarApiError> {
         debug!("Remove user [{}] from group [{}].", user, group);
 
-        let resp = self.execute_post(
-            self.build_url("/api/user_groups/remove_user", &vec![("login", user), ("name", group)]).as_str()
-        );
+        let resp = self.execute_post("/api/user_groups/remove_user", &[("login", user), ("name", group)])?;
 
-        SonarApi::assert_response(resp, format!("Error while removing user [{}] from user [{}].", user, group));
+        return SonarApi::check_response(resp, format!("Error while removing user [{}] from group [{}]", user, group));
     }
 
-    pub fn get_user_groups(&self, _user: &String) -> Vec<String> {
-        let resp = self.execute_get(self.build_url("/api/users/groups", &vec![("login", _user)]).as_str());
+    fn get_user_notifications(&self, login: &str) -> Result<Vec<SonarNotification>, SonarApiError> {
+        let resp = self.execute_get("/api/notifications/list", &[("login", login)])?;
 
-        let groups: SonarUserGroups =
-            SonarApi::assert_deserialize_response::<SonarUserGroups>(resp, format!("Cannot deserialize response retrieving groups of user [{}].", _user));
+        let notifications: SonarNotifications =
+            SonarApi::deserialize_response::<SonarNotifications>(resp, format!("Cannot deserialize notifications of user [{}]", login))?;
 
-        if groups.paging.page_size == groups.paging.total {
-            panic!(format!("Pagination of user groups is not supported. The user is [{}].", _user))
-        }
+        return Ok(notifications.notifications);
+    }
+
+    fn add_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError> {
+        debug!("Add notification [{}] to user [{}].", notification, login);
+
+        let resp = self.execute_post("/api/notifications/add", &SonarApi::notification_params(login, notification))?;
 
-        return groups.groups.iter().map(|member_ship| member_ship.name.to_string()).collect::<Vec<String>>();
+        return SonarApi::check_response(resp, format!("Error while adding notification [{}] to user [{}]", notification, login));
     }
 
-    pub fn get_permission_templates(&self) -> SonarPermissionTemplates {
-        let resp = self.execute_get(self.build_url("/api/permissions/search_templates", &vec![("q", DEFAULT_TEMPLATE_NAME)]).as_str());
+    fn remove_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError> {
+        debug!("Remove notification [{}] from user [{}].", notification, login);
 
-        return SonarApi::assert_deserialize_response::<SonarPermissionTemplates>(resp, "Cannot deserialize permission templates.".to_string());
+        let resp = self.execute_post("/api/notifications/remove", &SonarApi::notification_params(login, notification))?;
+
+        return SonarApi::check_response(resp, format!("Error while removing notification [{}] from user [{}]", notification, login));
     }
 
-    pub fn change_user_password(&mut self, user: &String, password: &String) {
-        if user.eq(&self.username) {
-            let resp = self.execute_post(
-                self.build_url(
-                    "/api/users/change_password",
-                    &vec![("login", user), ("password", password), ("previousPassword", self.password.as_str())],
-                ).as_str()
-            );
+    fn get_rule(&self, key: &str) -> Result<Option<SonarRule>, SonarApiError> {
+        let resp = self.execute_get("/api/rules/show", &[("key", key)])?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
 
-            SonarApi::assert_response(resp, format!("Error while changing user's password [{}].", user));
+        let details: SonarRuleDetails = SonarApi::deserialize_response::<SonarRuleDetails>(resp, format!("Cannot deserialize response retrieving rule [{}]", key))?;
 
-            self.password = password.to_string();
-        } else {
-            let resp =
-                self.execute_post(
-                    self.build_url("/api/users/change_password", &vec![("login", user), ("password", password)]).as_str()
-                );
+        return Ok(Some(details.rule));
+    }
 
-            SonarApi::assert_response(resp, format!("Error while changing user's password [{}].", user));
+    // Custom rules cannot be searched directly, they are searched for every rule template.
+    fn get_custom_rules(&self) -> Result<Vec<SonarRule>, SonarApiError> {
+        let templates = self.get_all_pages(
+            "/api/rules/search",
+            &[("is_template", "true")],
+            |page: SonarRules| (SonarPaging { page_index: page.p, page_size: page.ps, total: page.total }, page.rules),
+            "Cannot deserialize response retrieving rule templates".to_string(),
+        )?;
+
+        let mut rules = Vec::new();
+        for template in templates {
+            rules.extend(self.get_all_pages(
+                "/api/rules/search",
+                &[("template_key", template.key.as_str())],
+                |page: SonarRules| (SonarPaging { page_index: page.p, page_size: page.ps, total: page.total }, page.rules),
+                format!("Cannot deserialize response retrieving rules of template [{}]", template.key),
+            )?.into_iter().filter(|rule| !rule.is_removed()));
         }
+
+        return Ok(rules);
     }
 
-    pub fn generate_user_token(&self, user: &String, name: &String) -> String {
-        let resp = self.execute_post(
-            self.build_url("/api/user_tokens/generate", &vec![("login", user), ("name", name)]).as_str()
-        );
+    fn create_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError> {
+        debug!("Creating rule [{}].", rule.key());
+
+        let rule_params = SonarApi::rule_params(&rule.params);
+        let mut params = SonarApi::custom_rule_params(rule, &rule_params);
+        params.push(("custom_key", rule.custom_key.as_str()));
+        params.push(("template_key", rule.template_key.as_str()));
 
-        return SonarApi::assert_deserialize_response::<SonarGeneratedToken>(resp, format!("Error while generating user token [{}].", name)).token;
+        if let Some(rule_type) = &rule.rule_type {
+            params.push(("type", rule_type.as_str()));
+        }
+
+        let resp = self.execute_post("/api/rules/create", &params)?;
+
+        return SonarApi::check_response(resp, format!("Error while creating rule [{}]", rule.key()));
     }
 
-    fn execute_get(&self, path: &str) -> reqwest::blocking::Response {
-        return reqwest::blocking::Client::new()
-            .get(path)
-            .basic_auth(&self.username, Some(&self.password))
-            .send()
-            .expect("Error while connecting to SonarQube.");
+    fn update_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError> {
+        debug!("Updating rule [{}].", rule.key());
+
+        let key = rule.key();
+        let rule_params = SonarApi::rule_params(&rule.params);
+        let mut params = SonarApi::custom_rule_params(rule, &rule_params);
+        params.push(("key", key.as_str()));
+
+        let resp = self.execute_post("/api/rules/update", &params)?;
+
+        return SonarApi::check_response(resp, format!("Error while updating rule [{}]", key));
     }
 
-    fn execute_post(&self, path: &str) -> reqwest::blocking::Response {
-        return reqwest::blocking::Client::new()
-            .post(path)
-            .basic_auth(&self.username, Some(&self.password))
-            .send()
-            .expect("Error while connecting to SonarQube.");
+    fn delete_rule(&self, key: &str) -> Result<(), SonarApiError> {
+        debug!("Deleting rule [{}].", key);
+
+        let resp = self.execute_post("/api/rules/delete", &[("key", key)])?;
+
+        return SonarApi::check_response(resp, format!("Error while deleting rule [{}]", key));
     }
 
-    fn build_url(&self, path: &str, iter: &Vec<(&str, &str)>) -> String {
-        return reqwest::Url::parse_with_params(&format!("{}{}", self.url, &path), iter).unwrap().to_string();
+    fn get_quality_profiles(&self) -> Result<Vec<SonarQualityProfile>, SonarApiError> {
+        let resp = self.execute_get("/api/qualityprofiles/search", &[])?;
+
+        let profiles: SonarQualityProfiles = SonarApi::deserialize_response::<SonarQualityProfiles>(resp, "Cannot deserialize quality profiles".to_string())?;
+
+        return Ok(profiles.profiles);
     }
 
-    fn assert_response(resp: reqwest::blocking::Response, msg: String) {
+    fn backup_quality_profile(&self, language: &str, name: &str) -> Result<String, SonarApiError> {
+        let resp = self.execute_get("/api/qualityprofiles/backup", &[("language", language), ("qualityProfile", name)])?;
+
         if !resp.status().is_success() {
-            panic!(format!("{}.\nResponse:\n{:#?}", msg, resp.json::<SonarErrors>()));
+            return Err(SonarApiError::from_response(format!("Error while backing up quality profile [{}] of [{}]", name, language), resp));
         }
+
+        return resp.text()
+            .map_err(|error| SonarApiError::new(format!("Cannot read the backup of quality profile [{}]: {}", name, SonarApi::describe_error(&error))));
     }
 
-    fn assert_deserialize_response<T: DeserializeOwned>(resp: reqwest::blocking::Response, msg: String) -> T {
-        if !resp.status().is_success() {
-            // TODO avoid panic
-            panic!(format!("{}.\nResponse:\n{:#?}", msg, resp.json::<SonarErrors>()));
-        } else {
-            return resp.json::<T>()
-                .expect(msg.as_str());
+    fn restore_quality_profile(&self, backup: &str) -> Result<(), SonarApiError> {
+        debug!("Restoring quality profile.");
+
+        let part = reqwest::blocking::multipart::Part::text(backup.to_string()).file_name("backup.xml");
+        let resp = self.execute_multipart("/api/qualityprofiles/restore", reqwest::blocking::multipart::Form::new().part("backup", part))?;
+
+        return SonarApi::check_response(resp, "Error while restoring quality profile".to_string());
+    }
+}
+
+#[derive(Debug)]
+pub struct SonarApiError {
+    pub message: String,
+    pub errors: Option<SonarErrors>,
+}
+
+impl SonarApiError {
+    pub fn new(message: String) -> SonarApiError {
+        SonarApiError { message, errors: None }
+    }
+
+    fn from_response(message: String, resp: reqwest::blocking::Response) -> SonarApiError {
+        let status = resp.status();
+
+        SonarApiError { message: format!("{} (status {})", message, status), errors: resp.json::<SonarErrors>().ok() }
+    }
+}
+
+impl fmt::Display for SonarApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+
+        let messages: Vec<&str> = self.errors.iter()
+            .flat_map(|errors| errors.errors.iter().flatten())
+            .map(|error| error.msg.as_str())
+            .collect();
+
+        if !messages.is_empty() {
+            write!(f, ": {}", messages.join(", "))?;
         }
+
+        return Ok(());
     }
+}
 
-    fn get_group_by_name(&self, _name: &str) -> Option<SonarGroup> {
-        let resp = self.execute_get(self.build_url("/api/user_groups/search", &vec![("q", _name)]).as_str());
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+    use std::time::{Duration, Instant};
 
-        let groups: SonarGroups = resp.json::<SonarGroups>()
-            .expect("Cannot deserialize response.");
+    use tiny_http::{Header, Response, Server};
 
-        for group in groups.groups {
-            if group.name == _name {
-                return Some(group);
+    use super::{SonarApi, ADMIN_USER};
+    use secret_string::SecretString;
+    use sonar_api_model::{SonarProperty, SonarUser};
+    use sonar_client::SonarClient;
+
+    struct RecordedRequest {
+        method: String,
+        url: String,
+        content_type: Option<String>,
+        body: String,
+    }
+
+    // Starts a server answering the specified responses (status and JSON body) in order, received requests are recorded.
+    // Throttled requests (429) are asked to be sent again after a second.
+    fn mock_server(responses: Vec<(u16, &'static str)>) -> (SonarApi, Receiver<RecordedRequest>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr().to_ip().unwrap());
+        let (sender, receiver) = channel();
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let mut request = server.recv().unwrap();
+
+                let mut request_body = String::new();
+                request.as_reader().read_to_string(&mut request_body).unwrap();
+
+                sender.send(RecordedRequest {
+                    method: request.method().to_string(),
+                    url: request.url().to_string(),
+                    content_type: request.headers().iter()
+                        .find(|header| header.field.equiv("Content-Type"))
+                        .map(|header| header.value.to_string()),
+                    body: request_body,
+                }).unwrap();
+
+                let mut response = Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+                if status == 429 {
+                    response = response.with_header("Retry-After: 1".parse::<Header>().unwrap());
+                }
+                request.respond(response).unwrap();
             }
+        });
+
+        return (SonarApi::new(url, ADMIN_USER.to_string(), SecretString::secret("admin".to_string()), 1), receiver);
+    }
+
+    fn user(login: &str, password: Option<&str>) -> SonarUser {
+        SonarUser {
+            login: login.to_string(),
+            name: "John Doe".to_string(),
+            password: password.map(|password| SecretString::secret(password.to_string())),
+            groups: vec![],
+            local: None,
+            external_identity: None,
+            external_provider: None,
+            password_policy: Default::default(),
+            active: None,
+            anonymize: false,
+            email: None,
+            scm_accounts: None,
+            notifications: None,
         }
+    }
+
+    #[test]
+    fn sends_post_parameters_as_form_body() {
+        let (sonar_api, requests) = mock_server(vec![(204, "")]);
+
+        let property = SonarProperty { name: "sonar.secret".to_string(), value: SecretString::secret("p&ss#w=rd".to_string()) };
+        sonar_api.set_property(&property).unwrap();
+
+        let update = requests.recv().unwrap();
+        assert_eq!(update.method, "POST");
+        assert_eq!(update.url, "/api/settings/set");
+        assert_eq!(update.content_type.as_deref(), Some("application/x-www-form-urlencoded"));
+        assert_eq!(update.body, "key=sonar.secret&value=p%26ss%23w%3Drd");
+    }
+
+    #[test]
+    fn creates_user_on_the_server() {
+        let (sonar_api, requests) = mock_server(vec![(200, r#"{"user": {"login": "jdoe"}}"#)]);
+
+        sonar_api.create_user(&user("jdoe", Some("s3cr&t"))).unwrap();
+
+        let creation = requests.recv().unwrap();
+        assert_eq!(creation.url, "/api/users/create");
+        assert_eq!(creation.body, "login=jdoe&name=John+Doe&password=s3cr%26t");
+    }
+
+    #[test]
+    fn repeats_scm_accounts() {
+        let (sonar_api, requests) = mock_server(vec![(200, "{}")]);
+
+        let mut bot = user("bot", Some("secret"));
+        bot.scm_accounts = Some(vec!["bot".to_string(), "bot@example.com".to_string()]);
+        sonar_api.create_user(&bot).unwrap();
+
+        let creation = requests.recv().unwrap();
+        assert_eq!(creation.body, "login=bot&name=John+Doe&scmAccount=bot&scmAccount=bot%40example.com&password=secret");
+    }
+
+    #[test]
+    fn fetches_every_page() {
+        let (sonar_api, requests) = mock_server(vec![
+            (200, r#"{"paging": {"pageIndex": 1, "pageSize": 1, "total": 2}, "groups": [{"id": 1, "name": "developers", "description": "", "selected": true, "default": false}]}"#),
+            (200, r#"{"paging": {"pageIndex": 2, "pageSize": 1, "total": 2}, "groups": [{"id": 2, "name": "sonar-users", "description": "", "selected": true, "default": true}]}"#),
+        ]);
+
+        let groups = sonar_api.get_user_groups("jdoe").unwrap();
+
+        assert_eq!(groups, vec!["developers", "sonar-users"]);
+        assert_eq!(requests.recv().unwrap().url, "/api/users/groups?login=jdoe&p=1&ps=100");
+        assert_eq!(requests.recv().unwrap().url, "/api/users/groups?login=jdoe&p=2&ps=100");
+    }
+
+    #[test]
+    fn searches_every_page_for_the_exact_login() {
+        let (sonar_api, requests) = mock_server(vec![
+            (200, r#"{"paging": {"pageIndex": 1, "pageSize": 1, "total": 2}, "users": [{"login": "jdoe2", "name": "Jane Doe", "groups": []}]}"#),
+            (200, r#"{"paging": {"pageIndex": 2, "pageSize": 1, "total": 2}, "users": [{"login": "jdoe", "name": "John Doe", "groups": []}]}"#),
+        ]);
+
+        let user = sonar_api.get_user("jdoe").unwrap();
+
+        assert_eq!(user.map(|user| user.name), Some("John Doe".to_string()));
+        assert_eq!(requests.recv().unwrap().url, "/api/users/search?q=jdoe&deactivated=false&p=1&ps=100");
+        assert_eq!(requests.recv().unwrap().url, "/api/users/search?q=jdoe&deactivated=false&p=2&ps=100");
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn finds_deactivated_users() {
+        let (sonar_api, requests) = mock_server(vec![
+            (200, r#"{"paging": {"pageIndex": 1, "pageSize": 100, "total": 0}, "users": []}"#),
+            (200, r#"{"paging": {"pageIndex": 1, "pageSize": 100, "total": 1}, "users": [{"login": "jdoe", "name": "jdoe", "active": false, "groups": []}]}"#),
+        ]);
+
+        let user = sonar_api.get_user("jdoe").unwrap().unwrap();
+
+        assert!(!user.is_active());
+        assert_eq!(requests.recv().unwrap().url, "/api/users/search?q=jdoe&deactivated=false&p=1&ps=100");
+        assert_eq!(requests.recv().unwrap().url, "/api/users/search?q=jdoe&deactivated=true&p=1&ps=100");
+    }
+
+    #[test]
+    fn sends_throttled_requests_again_after_the_delay() {
+        let (sonar_api, requests) = mock_server(vec![(429, ""), (204, "")]);
+        let started = Instant::now();
+
+        let property = SonarProperty { name: "sonar.forceAuthentication".to_string(), value: SecretString::plain("true".to_string()) };
+        sonar_api.set_property(&property).unwrap();
+
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(requests.recv().unwrap().body, "key=sonar.forceAuthentication&value=true");
+        assert_eq!(requests.recv().unwrap().body, "key=sonar.forceAuthentication&value=true");
+    }
+
+    #[test]
+    fn gives_up_when_requests_are_still_throttled() {
+        let (sonar_api, requests) = mock_server(vec![(429, ""); 5]);
+
+        let error = sonar_api.add_user_to_group("jdoe", "developers").unwrap_err();
+
+        assert!(error.to_string().contains("status 429 Too Many Requests"), "{}", error);
+        assert_eq!(requests.iter().count(), 5);
+    }
+
+    #[test]
+    fn finds_properties_with_several_values_or_fields() {
+        let (sonar_api, _requests) = mock_server(vec![(200, r#"{"settings": [
+            {"key": "sonar.core.serverBaseURL", "value": "https://sonar"},
+            {"key": "sonar.global.exclusions", "values": ["**/generated/**"]},
+            {"key": "sonar.issue.ignore.multicriteria", "fieldValues": [{"ruleKey": "java:S106", "resourceKey": "**/*.java"}]},
+            {"key": "sonar.java.file.suffixes", "values": [".java"], "inherited": true}
+        ]}"#)]);
+
+        let properties = sonar_api.get_multi_valued_properties().unwrap();
+
+        assert_eq!(properties.into_iter().collect::<Vec<String>>(), vec!["sonar.global.exclusions", "sonar.issue.ignore.multicriteria"]);
+    }
+
+    #[test]
+    fn reports_errors_of_the_server() {
+        let (sonar_api, _requests) = mock_server(vec![(400, r#"{"errors": [{"msg": "Group 'developers' does not exist"}]}"#)]);
+
+        let error = sonar_api.add_user_to_group("jdoe", "developers").unwrap_err();
 
-        return None;
+        assert_eq!(error.to_string(), "Error while adding user [jdoe] to group [developers] (status 400 Bad Request): Group 'developers' does not exist");
     }
 }
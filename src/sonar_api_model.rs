@@ -1,8 +1,17 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::vec::Vec;
 
+use config_file_model::CustomRule;
 use config_file_model::Group;
 use config_file_model::Property;
+use config_file_model::Notification;
 use config_file_model::User;
+use config_file_model::PasswordPolicy;
+use config_file_model::UserState;
+use secret_string::{is_sensitive_property, SecretString};
+
+pub const DEFAULT_NOTIFICATION_CHANNEL: &str = "EmailNotificationChannel";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SonarPaging {
@@ -15,25 +24,30 @@ pub struct SonarPaging {
     pub total: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SonarGroupCreationRequest {
     pub name: String,
     pub description: String,
 
     #[serde(skip_deserializing)]
     pub permissions: Vec<String>,
+
+    // logins of the members, not managed from the group when not specified
+    #[serde(skip_deserializing)]
+    pub members: Option<Vec<String>>,
 }
 
 impl SonarGroupCreationRequest {
-    pub fn from_configuration_group(group: &Group,  _resolver: fn(&String) -> String) -> SonarGroupCreationRequest {
+    pub fn from_configuration_group(group: &Group,  _resolver: &dyn Fn(&String) -> String) -> SonarGroupCreationRequest {
         SonarGroupCreationRequest {
             name: _resolver(&group.name),
             description: _resolver(&group.description),
-            permissions: group.permissions.clone().into_iter().map(|permission| _resolver(&permission)).rev().collect()
+            permissions: group.permissions.clone().into_iter().map(|permission| _resolver(&permission)).rev().collect(),
+            members: group.members.as_ref().map(|members| members.iter().map(_resolver).collect()),
         }
     }
 
-    pub fn from_configuration_groups(groups: &Vec<Group>,  _resolver: fn(&String) -> String) -> Vec<SonarGroupCreationRequest> {
+    pub fn from_configuration_groups(groups: &[Group],  _resolver: &dyn Fn(&String) -> String) -> Vec<SonarGroupCreationRequest> {
         let mut mapped = Vec::new();
         for i in 0..groups.len() {
             mapped.push(SonarGroupCreationRequest::from_configuration_group(groups.get(i).unwrap(), _resolver));
@@ -49,7 +63,7 @@ pub struct SonarGroups {
     pub groups: Vec<SonarGroup>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SonarGroup {
     pub id: usize,
     pub name: String,
@@ -59,21 +73,24 @@ pub struct SonarGroup {
     pub permissions: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SonarProperty {
     pub name: String,
-    pub value: String,
+    pub value: SecretString,
 }
 
 impl SonarProperty {
-    pub fn from_configuration_property(property: &Property, _resolver: fn(&String) -> String) -> SonarProperty {
+    pub fn from_configuration_property(property: &Property, _resolver: &dyn Fn(&String) -> String) -> SonarProperty {
+        let name = _resolver(&property.name);
+        let value = _resolver(&property.value);
+
         SonarProperty {
-            name: _resolver(&property.name),
-            value: _resolver(&property.value),
+            value: if property.sensitive.unwrap_or(false) || is_sensitive_property(&name) { SecretString::secret(value) } else { SecretString::plain(value) },
+            name,
         }
     }
 
-    pub fn from_configuration_properties(properties: &Vec<Property>, _resolver: fn(&String) -> String) -> Vec<SonarProperty> {
+    pub fn from_configuration_properties(properties: &[Property], _resolver: &dyn Fn(&String) -> String) -> Vec<SonarProperty> {
         let mut mapped = Vec::new();
         for i in 0..properties.len() {
             mapped.push(SonarProperty::from_configuration_property(properties.get(i).unwrap(), _resolver));
@@ -84,24 +101,127 @@ impl SonarProperty {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct SonarSettings {
+    pub settings: Vec<SonarSetting>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SonarSetting {
+    pub key: String,
+    pub value: Option<String>,
+    pub values: Option<Vec<String>>,
+
+    #[serde(rename = "fieldValues")]
+    pub field_values: Option<Vec<BTreeMap<String, String>>>,
+
+    pub inherited: Option<bool>,
+}
+
+impl SonarSetting {
+    pub fn is_multi_valued(&self) -> bool {
+        self.values.is_some() || self.field_values.is_some()
+    }
+
+    // Multi-valued settings are joined like they are set.
+    pub fn into_value(self) -> Option<String> {
+        match self.value {
+            Some(value) => Some(value),
+            None => self.values.map(|values| values.join(",")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SonarUser {
     pub login: String,
     pub name: String,
-    pub password: Option<String>,
+    pub password: Option<SecretString>,
     pub groups: Vec<String>,
+    pub local: Option<bool>,
+
+    #[serde(rename = "externalIdentity")]
+    pub external_identity: Option<String>,
+
+    #[serde(rename = "externalProvider")]
+    pub external_provider: Option<String>,
+
+    #[serde(skip_deserializing)]
+    pub password_policy: PasswordPolicy,
+
+    pub active: Option<bool>,
+
+    #[serde(skip_deserializing)]
+    pub anonymize: bool,
+
+    pub email: Option<String>,
+
+    #[serde(rename = "scmAccounts")]
+    pub scm_accounts: Option<Vec<String>>,
+
+    #[serde(skip_deserializing)]
+    pub notifications: Option<Vec<SonarNotification>>,
 }
 
 impl SonarUser {
-    pub fn from_configuration_user(user: &User, _resolver: fn(&String) -> String) -> SonarUser {
+    pub fn from_configuration_user(user: &User, _resolver: &dyn Fn(&String) -> String) -> SonarUser {
         SonarUser {
             login: _resolver(&user.login),
             name: _resolver(&user.name),
-            password: Some(_resolver(&user.password)),
-            groups: user.groups.clone().unwrap_or(Vec::new()).into_iter().map(|group| _resolver(&group)).collect(),
+            password: user.password.as_ref().map(|password| password.map(_resolver)),
+            groups: user.groups.clone().unwrap_or_default().into_iter().map(|group| _resolver(&group)).collect(),
+            local: Some(user.local.unwrap_or(true)),
+            external_identity: user.external_identity.as_ref().map(_resolver),
+            external_provider: user.external_provider.as_ref().map(_resolver),
+            password_policy: user.password_policy.unwrap_or_default(),
+            active: Some(user.state.unwrap_or_default() == UserState::Active),
+            anonymize: user.anonymize.unwrap_or(false),
+            email: user.email.as_ref().map(_resolver),
+            scm_accounts: user.scm_accounts.as_ref().map(|accounts| accounts.iter().map(_resolver).collect()),
+            notifications: user.notifications.as_ref().map(|notifications| SonarNotification::from_configuration_notifications(notifications, _resolver)),
         }
     }
 
-    pub fn from_configuration_users(users: &Vec<User>, _resolver: fn(&String) -> String) -> Vec<SonarUser> {
+    // The email is not managed when it's not specified.
+    pub fn has_same_email(&self, current: &SonarUser) -> bool {
+        self.email.is_none() || self.email == current.email
+    }
+
+    // SCM accounts are not managed when they are not specified, their order does not matter.
+    pub fn has_same_scm_accounts(&self, current: &SonarUser) -> bool {
+        match &self.scm_accounts {
+            None => true,
+            Some(scm_accounts) => {
+                let desired: BTreeSet<&String> = scm_accounts.iter().collect();
+                let current: BTreeSet<&String> = current.scm_accounts.iter().flatten().collect();
+
+                desired == current
+            }
+        }
+    }
+
+    // Users are active unless specified otherwise.
+    pub fn is_active(&self) -> bool {
+        self.active.unwrap_or(true)
+    }
+
+    // Users are local unless specified otherwise.
+    pub fn is_local(&self) -> bool {
+        self.local.unwrap_or(true)
+    }
+
+    // Returns whether the current user is authenticated the way this user must be. The external identity is only
+    // compared when it's specified.
+    pub fn has_same_identity(&self, current: &SonarUser) -> bool {
+        if self.is_local() {
+            return current.is_local();
+        }
+
+        return !current.is_local()
+            && self.external_provider == current.external_provider
+            && (self.external_identity.is_none() || self.external_identity == current.external_identity);
+    }
+
+    pub fn from_configuration_users(users: &[User], _resolver: &dyn Fn(&String) -> String) -> Vec<SonarUser> {
         let mut mapped = Vec::new();
         for i in 0..users.len() {
             mapped.push(SonarUser::from_configuration_user(users.get(i).unwrap(), _resolver));
@@ -111,6 +231,49 @@ impl SonarUser {
     }
 }
 
+// Attributes of a user to change, the other ones are left unchanged.
+#[derive(Debug, Default)]
+pub struct SonarUserUpdate {
+    pub login: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub scm_accounts: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SonarNotification {
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub channel: String,
+    pub project: Option<String>,
+}
+
+impl SonarNotification {
+    pub fn from_configuration_notifications(notifications: &[Notification], _resolver: &dyn Fn(&String) -> String) -> Vec<SonarNotification> {
+        notifications.iter()
+            .map(|notification| SonarNotification {
+                notification_type: _resolver(&notification.notification_type),
+                channel: notification.channel.as_ref().map(_resolver).unwrap_or_else(|| DEFAULT_NOTIFICATION_CHANNEL.to_string()),
+                project: notification.project.as_ref().map(_resolver),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for SonarNotification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.project {
+            Some(project) => write!(f, "{}/{}@{}", self.notification_type, project, self.channel),
+            None => write!(f, "{}@{}", self.notification_type, self.channel),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SonarNotifications {
+    pub notifications: Vec<SonarNotification>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SonarUsers {
     pub paging: SonarPaging,
@@ -153,49 +316,132 @@ pub struct SonarUserMembership {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SonarPermissionTemplates {
-    #[serde(rename(deserialize = "permissionTemplates"))]
-    pub permission_templates: Vec<SonarPermissionTemplate>,
+pub struct SonarQualityProfiles {
+    pub profiles: Vec<SonarQualityProfile>,
+}
 
-    #[serde(rename(deserialize = "defaultTemplates"))]
-    pub default_templates: Vec<SonarDefaultTemplate>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SonarQualityProfile {
+    pub key: String,
+    pub name: String,
+    pub language: String,
 
-    pub permissions: Vec<SonarPermission>,
+    #[serde(rename = "isBuiltIn")]
+    pub is_built_in: bool,
+
+    #[serde(rename = "isDefault")]
+    pub is_default: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SonarDefaultTemplate {
-    #[serde(rename(deserialize = "templateId"))]
-    pub template_id: String,
+// Custom rule to create from a rule template.
+#[derive(Debug, Clone)]
+pub struct SonarCustomRule {
+    pub template_key: String,
+    pub custom_key: String,
+    pub name: String,
+    pub severity: Option<String>,
+    pub rule_type: Option<String>,
+    pub markdown_description: String,
+    pub params: BTreeMap<String, String>,
+}
+
+impl SonarCustomRule {
+    pub fn from_configuration_custom_rule(rule: &CustomRule, _resolver: &dyn Fn(&String) -> String) -> SonarCustomRule {
+        SonarCustomRule {
+            template_key: _resolver(&rule.template_key),
+            custom_key: _resolver(&rule.custom_key),
+            name: _resolver(&rule.name),
+            severity: rule.severity.as_ref().map(_resolver),
+            rule_type: rule.rule_type.as_ref().map(_resolver),
+            markdown_description: _resolver(&rule.markdown_description),
+            params: rule.params.iter().flatten().map(|(key, value)| (key.to_string(), _resolver(value))).collect(),
+        }
+    }
+
+    pub fn from_configuration_custom_rules(rules: &[CustomRule], _resolver: &dyn Fn(&String) -> String) -> Vec<SonarCustomRule> {
+        rules.iter().map(|rule| SonarCustomRule::from_configuration_custom_rule(rule, _resolver)).collect()
+    }
 
-    pub qualifier: String,
+    // Key of the rule: the custom key in the repository of the template.
+    pub fn key(&self) -> String {
+        let repository = self.template_key.split(':').next().unwrap_or_default();
+
+        format!("{}:{}", repository, self.custom_key)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SonarPermission {
-    pub key: String,
-    pub name: String,
-    pub description: String,
+pub struct SonarRuleDetails {
+    pub rule: SonarRule,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SonarPermissionTemplate {
-    pub id: String,
+pub struct SonarRules {
+    pub total: usize,
+    pub p: usize,
+    pub ps: usize,
+    pub rules: Vec<SonarRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SonarRule {
+    pub key: String,
     pub name: String,
-    pub description: String,
-    pub permissions: Vec<SonarPermissionTemplatePermission>,
+    pub severity: Option<String>,
+
+    #[serde(rename = "type")]
+    pub rule_type: Option<String>,
+
+    #[serde(rename = "mdDesc")]
+    pub markdown_description: Option<String>,
+
+    // only set on custom rules
+    #[serde(rename = "templateKey")]
+    pub template_key: Option<String>,
+
+    pub status: Option<String>,
+
+    #[serde(default)]
+    pub params: Vec<SonarRuleParam>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SonarPermissionTemplatePermission {
+impl SonarRule {
+    // Deleted rules are kept by SonarQube with this status, they are reactivated when created again.
+    pub fn is_removed(&self) -> bool {
+        self.status.as_deref() == Some("REMOVED")
+    }
+
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params.iter().find(|param| param.key == key).and_then(|param| param.default_value.as_deref())
+    }
+}
+
+// Parameter of a rule, the value of a custom rule is its default value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SonarRuleParam {
     pub key: String,
+
+    #[serde(rename = "defaultValue")]
+    pub default_value: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SonarTemplateGroups {
+    pub paging: SonarPaging,
+    pub groups: Vec<SonarTemplateGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SonarTemplateGroup {
+    pub name: String,
+    pub permissions: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SonarGeneratedToken {
     pub login: String,
     pub name: String,
-    pub token: String,
+    pub token: SecretString,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -207,3 +453,45 @@ pub struct SonarErrors {
 pub struct SonarError {
     pub msg: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(configuration: &str) -> SonarUser {
+        SonarUser::from_configuration_user(&serde_yaml::from_str(configuration).unwrap(), &|value| value.to_string())
+    }
+
+    #[test]
+    fn users_are_local_and_their_password_always_set_by_default() {
+        let jdoe = user("{login: jdoe, name: John Doe}");
+
+        assert!(jdoe.is_local());
+        assert!(jdoe.password.is_none());
+        assert_eq!(jdoe.password_policy, PasswordPolicy::Always);
+    }
+
+    #[test]
+    fn reads_the_password_policy() {
+        let jdoe = user("{login: jdoe, name: John Doe, password: secret, passwordPolicy: createOnly}");
+
+        assert_eq!(jdoe.password.map(|password| password.expose().to_string()), Some("secret".to_string()));
+        assert_eq!(jdoe.password_policy, PasswordPolicy::CreateOnly);
+    }
+
+    #[test]
+    fn compares_the_identity_of_users() {
+        let local = user("{login: jdoe, name: John Doe}");
+        let saml = user("{login: jdoe, name: John Doe, local: false, externalProvider: saml}");
+        let saml_identity = user("{login: jdoe, name: John Doe, local: false, externalProvider: saml, externalIdentity: john.doe}");
+        let ldap = user("{login: jdoe, name: John Doe, local: false, externalProvider: ldap}");
+
+        assert!(local.has_same_identity(&local));
+        assert!(!local.has_same_identity(&saml));
+        assert!(!saml.has_same_identity(&local));
+        assert!(!saml.has_same_identity(&ldap));
+        // the external identity is only compared when it's specified
+        assert!(saml.has_same_identity(&saml_identity));
+        assert!(!saml_identity.has_same_identity(&saml));
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use secret_string::SecretString;
+use sonar_api::SonarApiError;
+use sonar_api_model::{SonarGroup, SonarGroupCreationRequest, SonarNotification, SonarProperty, SonarCustomRule, SonarQualityProfile, SonarRule, SonarTemplateGroup, SonarUser, SonarUserUpdate};
+
+// Operations on SonarQube used to reconcile it with the configuration file. They don't decide anything: what must be
+// created, updated or removed is decided by the reconciliation. SonarApi implements them with the web API, InMemorySonar
+// simulates them. Resources may be reconciled concurrently, the client is shared between threads.
+pub trait SonarClient: Sync {
+    // Returns the value explicitly set for the property, secured properties are never returned.
+    fn get_property_value(&self, key: &str) -> Result<Option<String>, SonarApiError>;
+
+    // Returns the values explicitly set for every property, secured properties are never returned.
+    fn get_property_values(&self) -> Result<BTreeMap<String, String>, SonarApiError>;
+
+    fn set_property(&self, property: &SonarProperty) -> Result<(), SonarApiError>;
+
+    // Returns the properties explicitly set with several values or fields, they cannot be set by set_property.
+    fn get_multi_valued_properties(&self) -> Result<BTreeSet<String>, SonarApiError>;
+
+    fn get_group_by_name(&self, name: &str) -> Result<Option<SonarGroup>, SonarApiError>;
+
+    fn get_groups(&self) -> Result<Vec<SonarGroup>, SonarApiError>;
+
+    fn create_group(&self, group: &SonarGroupCreationRequest) -> Result<(), SonarApiError>;
+
+    fn update_group_description(&self, current_group: &SonarGroup, description: &str) -> Result<(), SonarApiError>;
+
+    fn delete_group(&self, name: &str) -> Result<(), SonarApiError>;
+
+    // Returns the groups of the default permission template with their permissions.
+    fn get_template_groups(&self) -> Result<Vec<SonarTemplateGroup>, SonarApiError>;
+
+    fn add_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError>;
+
+    fn remove_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError>;
+
+    // Returns the user, active or deactivated.
+    fn get_user(&self, login: &str) -> Result<Option<SonarUser>, SonarApiError>;
+
+    // Returns every user, active or deactivated, with the groups they are member of.
+    fn get_users(&self) -> Result<Vec<SonarUser>, SonarApiError>;
+
+    // Creates the user, a deactivated user is reactivated.
+    fn create_user(&self, user: &SonarUser) -> Result<(), SonarApiError>;
+
+    fn update_user(&self, update: &SonarUserUpdate) -> Result<(), SonarApiError>;
+
+    fn update_identity_provider(&self, user: &SonarUser) -> Result<(), SonarApiError>;
+
+    fn change_user_password(&self, login: &str, password: &SecretString) -> Result<(), SonarApiError>;
+
+    fn deactivate_user(&self, login: &str, anonymize: bool) -> Result<(), SonarApiError>;
+
+    fn get_user_groups(&self, login: &str) -> Result<Vec<String>, SonarApiError>;
+
+    fn get_group_members(&self, group: &str) -> Result<Vec<String>, SonarApiError>;
+
+    fn add_user_to_group(&self, login: &str, group: &str) -> Result<(), SonarApiError>;
+
+    fn remove_user_from_group(&self, login: &str, group: &str) -> Result<(), SonarApiError>;
+
+    fn get_user_notifications(&self, login: &str) -> Result<Vec<SonarNotification>, SonarApiError>;
+
+    fn add_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError>;
+
+    fn remove_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError>;
+
+    // Returns the rule, deleted rules included (see SonarRule::is_removed).
+    fn get_rule(&self, key: &str) -> Result<Option<SonarRule>, SonarApiError>;
+
+    // Returns every rule created from a rule template that is not deleted.
+    fn get_custom_rules(&self) -> Result<Vec<SonarRule>, SonarApiError>;
+
+    // Creates the rule from its template, a deleted rule having the same key is reactivated.
+    fn create_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError>;
+
+    // Updates the name, the description, the severity and the parameters of the rule.
+    fn update_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError>;
+
+    fn delete_rule(&self, key: &str) -> Result<(), SonarApiError>;
+
+    fn get_quality_profiles(&self) -> Result<Vec<SonarQualityProfile>, SonarApiError>;
+
+    // Returns the XML backup of the quality profile: its rules and their parameters.
+    fn backup_quality_profile(&self, language: &str, name: &str) -> Result<String, SonarApiError>;
+
+    // Creates or replaces the quality profile named in the XML backup.
+    fn restore_quality_profile(&self, backup: &str) -> Result<(), SonarApiError>;
+}
@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, MutexGuard};
+
+use secret_string::SecretString;
+use sonar_api::SonarApiError;
+use sonar_api_model::{SonarGroup, SonarGroupCreationRequest, SonarNotification, SonarCustomRule, SonarProperty, SonarQualityProfile, SonarRule, SonarTemplateGroup, SonarUser, SonarUserUpdate};
+use sonar_client::SonarClient;
+
+// State of SonarQube fetched once before reconciling: properties, groups, users with their groups and permissions of
+// the template. Resources are compared to it instead of querying SonarQube for each of them. Changes are sent to
+// SonarQube, what they touch is then read from SonarQube again.
+pub struct SonarSnapshot<'a> {
+    client: &'a dyn SonarClient,
+    properties: BTreeMap<String, String>,
+    groups: Vec<SonarGroup>,
+    users: Vec<SonarUser>,
+    template_groups: Vec<SonarTemplateGroup>,
+    stale: Mutex<Stale>,
+}
+
+// What has been changed since the snapshot was fetched.
+#[derive(Default)]
+struct Stale {
+    properties: BTreeSet<String>,
+    // the group, its permissions or its members
+    groups: BTreeSet<String>,
+    // the user or its groups
+    users: BTreeSet<String>,
+    template: bool,
+}
+
+impl<'a> SonarSnapshot<'a> {
+    pub fn fetch(client: &'a dyn SonarClient) -> Result<SonarSnapshot<'a>, SonarApiError> {
+        debug!("Fetching the state of SonarQube.");
+
+        return Ok(SonarSnapshot {
+            client,
+            properties: client.get_property_values()?,
+            groups: client.get_groups()?,
+            users: client.get_users()?,
+            template_groups: client.get_template_groups()?,
+            stale: Mutex::new(Stale::default()),
+        });
+    }
+
+    fn stale(&self) -> MutexGuard<'_, Stale> {
+        self.stale.lock().expect("Cannot read the changes of the snapshot.")
+    }
+
+    fn snapshot_user(&self, login: &str) -> Option<&SonarUser> {
+        self.users.iter().find(|user| user.login == login)
+    }
+}
+
+impl<'a> SonarClient for SonarSnapshot<'a> {
+    fn get_property_value(&self, key: &str) -> Result<Option<String>, SonarApiError> {
+        if self.stale().properties.contains(key) {
+            return self.client.get_property_value(key);
+        }
+
+        return Ok(self.properties.get(key).cloned());
+    }
+
+    fn get_property_values(&self) -> Result<BTreeMap<String, String>, SonarApiError> {
+        if !self.stale().properties.is_empty() {
+            return self.client.get_property_values();
+        }
+
+        return Ok(self.properties.clone());
+    }
+
+    fn get_multi_valued_properties(&self) -> Result<BTreeSet<String>, SonarApiError> {
+        return self.client.get_multi_valued_properties();
+    }
+
+    fn set_property(&self, property: &SonarProperty) -> Result<(), SonarApiError> {
+        self.stale().properties.insert(property.name.to_string());
+
+        return self.client.set_property(property);
+    }
+
+    fn get_group_by_name(&self, name: &str) -> Result<Option<SonarGroup>, SonarApiError> {
+        if self.stale().groups.contains(name) {
+            return self.client.get_group_by_name(name);
+        }
+
+        return Ok(self.groups.iter().find(|group| group.name == name).cloned());
+    }
+
+    fn get_groups(&self) -> Result<Vec<SonarGroup>, SonarApiError> {
+        if !self.stale().groups.is_empty() {
+            return self.client.get_groups();
+        }
+
+        return Ok(self.groups.clone());
+    }
+
+    fn create_group(&self, group: &SonarGroupCreationRequest) -> Result<(), SonarApiError> {
+        self.stale().groups.insert(group.name.to_string());
+
+        return self.client.create_group(group);
+    }
+
+    fn update_group_description(&self, current_group: &SonarGroup, description: &str) -> Result<(), SonarApiError> {
+        self.stale().groups.insert(current_group.name.to_string());
+
+        return self.client.update_group_description(current_group, description);
+    }
+
+    fn delete_group(&self, name: &str) -> Result<(), SonarApiError> {
+        let mut stale = self.stale();
+        stale.groups.insert(name.to_string());
+        stale.template = true;
+        drop(stale);
+
+        return self.client.delete_group(name);
+    }
+
+    fn get_template_groups(&self) -> Result<Vec<SonarTemplateGroup>, SonarApiError> {
+        if self.stale().template {
+            return self.client.get_template_groups();
+        }
+
+        return Ok(self.template_groups.clone());
+    }
+
+    fn add_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError> {
+        self.stale().template = true;
+
+        return self.client.add_permission_to_group(group, permission);
+    }
+
+    fn remove_permission_to_group(&self, group: &str, permission: &str) -> Result<(), SonarApiError> {
+        self.stale().template = true;
+
+        return self.client.remove_permission_to_group(group, permission);
+    }
+
+    fn get_user(&self, login: &str) -> Result<Option<SonarUser>, SonarApiError> {
+        if self.stale().users.contains(login) {
+            return self.client.get_user(login);
+        }
+
+        return Ok(self.snapshot_user(login).cloned());
+    }
+
+    fn get_users(&self) -> Result<Vec<SonarUser>, SonarApiError> {
+        if !self.stale().users.is_empty() {
+            return self.client.get_users();
+        }
+
+        return Ok(self.users.clone());
+    }
+
+    fn create_user(&self, user: &SonarUser) -> Result<(), SonarApiError> {
+        self.stale().users.insert(user.login.to_string());
+
+        return self.client.create_user(user);
+    }
+
+    fn update_user(&self, update: &SonarUserUpdate) -> Result<(), SonarApiError> {
+        self.stale().users.insert(update.login.to_string());
+
+        return self.client.update_user(update);
+    }
+
+    fn update_identity_provider(&self, user: &SonarUser) -> Result<(), SonarApiError> {
+        self.stale().users.insert(user.login.to_string());
+
+        return self.client.update_identity_provider(user);
+    }
+
+    fn change_user_password(&self, login: &str, password: &SecretString) -> Result<(), SonarApiError> {
+        return self.client.change_user_password(login, password);
+    }
+
+    fn deactivate_user(&self, login: &str, anonymize: bool) -> Result<(), SonarApiError> {
+        self.stale().users.insert(login.to_string());
+
+        return self.client.deactivate_user(login, anonymize);
+    }
+
+    // Groups of the user are read again when the user or one of its groups changed.
+    fn get_user_groups(&self, login: &str) -> Result<Vec<String>, SonarApiError> {
+        let user = match self.snapshot_user(login) {
+            Some(user) => user,
+            None => return self.client.get_user_groups(login),
+        };
+
+        let stale = self.stale();
+        if stale.users.contains(login) || user.groups.iter().any(|group| stale.groups.contains(group)) {
+            drop(stale);
+
+            return self.client.get_user_groups(login);
+        }
+
+        return Ok(user.groups.clone());
+    }
+
+    // Members of the group are read again when the group or one of its members changed.
+    fn get_group_members(&self, group: &str) -> Result<Vec<String>, SonarApiError> {
+        let members: Vec<&SonarUser> = self.users.iter().filter(|user| user.groups.iter().any(|name| name == group)).collect();
+
+        let stale = self.stale();
+        if stale.groups.contains(group) || members.iter().any(|member| stale.users.contains(&member.login)) {
+            drop(stale);
+
+            return self.client.get_group_members(group);
+        }
+
+        return Ok(members.iter().map(|member| member.login.to_string()).collect());
+    }
+
+    fn add_user_to_group(&self, login: &str, group: &str) -> Result<(), SonarApiError> {
+        let mut stale = self.stale();
+        stale.users.insert(login.to_string());
+        stale.groups.insert(group.to_string());
+        drop(stale);
+
+        return self.client.add_user_to_group(login, group);
+    }
+
+    fn remove_user_from_group(&self, login: &str, group: &str) -> Result<(), SonarApiError> {
+        let mut stale = self.stale();
+        stale.users.insert(login.to_string());
+        stale.groups.insert(group.to_string());
+        drop(stale);
+
+        return self.client.remove_user_from_group(login, group);
+    }
+
+    // Notifications cannot be fetched for every user at once, they are read from SonarQube.
+    fn get_user_notifications(&self, login: &str) -> Result<Vec<SonarNotification>, SonarApiError> {
+        return self.client.get_user_notifications(login);
+    }
+
+    fn add_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError> {
+        return self.client.add_user_notification(login, notification);
+    }
+
+    fn remove_user_notification(&self, login: &str, notification: &SonarNotification) -> Result<(), SonarApiError> {
+        return self.client.remove_user_notification(login, notification);
+    }
+
+    // Rules and quality profiles are only read when they are declared, they are read from SonarQube.
+    fn get_rule(&self, key: &str) -> Result<Option<SonarRule>, SonarApiError> {
+        return self.client.get_rule(key);
+    }
+
+    fn get_custom_rules(&self) -> Result<Vec<SonarRule>, SonarApiError> {
+        return self.client.get_custom_rules();
+    }
+
+    fn create_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError> {
+        return self.client.create_custom_rule(rule);
+    }
+
+    fn update_custom_rule(&self, rule: &SonarCustomRule) -> Result<(), SonarApiError> {
+        return self.client.update_custom_rule(rule);
+    }
+
+    fn delete_rule(&self, key: &str) -> Result<(), SonarApiError> {
+        return self.client.delete_rule(key);
+    }
+
+    fn get_quality_profiles(&self) -> Result<Vec<SonarQualityProfile>, SonarApiError> {
+        return self.client.get_quality_profiles();
+    }
+
+    fn backup_quality_profile(&self, language: &str, name: &str) -> Result<String, SonarApiError> {
+        return self.client.backup_quality_profile(language, name);
+    }
+
+    fn restore_quality_profile(&self, backup: &str) -> Result<(), SonarApiError> {
+        return self.client.restore_quality_profile(backup);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SonarSnapshot;
+    use in_memory_sonar::InMemorySonar;
+    use sonar_api_model::SonarGroupCreationRequest;
+    use sonar_client::SonarClient;
+
+    fn developers() -> SonarGroupCreationRequest {
+        SonarGroupCreationRequest { name: "developers".to_string(), description: "Developers".to_string(), permissions: vec![], members: None }
+    }
+
+    #[test]
+    fn reads_the_state_fetched_once() {
+        let sonar = InMemorySonar::new();
+        let snapshot = SonarSnapshot::fetch(&sonar).unwrap();
+
+        // changed behind the snapshot
+        sonar.create_group(&developers()).unwrap();
+
+        assert!(snapshot.get_group_by_name("developers").unwrap().is_none());
+        assert_eq!(snapshot.get_user_groups("admin").unwrap(), vec!["sonar-administrators", "sonar-users"]);
+        assert_eq!(snapshot.get_group_members("sonar-users").unwrap(), vec!["admin"]);
+    }
+
+    #[test]
+    fn reads_again_what_is_changed() {
+        let sonar = InMemorySonar::new();
+        let snapshot = SonarSnapshot::fetch(&sonar).unwrap();
+
+        snapshot.create_group(&developers()).unwrap();
+        snapshot.add_user_to_group("admin", "developers").unwrap();
+        snapshot.add_permission_to_group("developers", "scan").unwrap();
+
+        assert!(snapshot.get_group_by_name("developers").unwrap().is_some());
+        assert_eq!(snapshot.get_user_groups("admin").unwrap(), vec!["developers", "sonar-administrators", "sonar-users"]);
+        assert_eq!(snapshot.get_group_members("developers").unwrap(), vec!["admin"]);
+        assert!(snapshot.get_template_groups().unwrap().iter().any(|group| group.name == "developers" && group.permissions == vec!["scan"]));
+    }
+}
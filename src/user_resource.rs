@@ -0,0 +1,349 @@
+use config_file_model::PasswordPolicy;
+use membership::MembershipDiff;
+use report::{Action, ResourceKind};
+use resource::{Change, Resource};
+use sonar_api::{is_built_in_group, SonarApiError, ADMIN_USER};
+use sonar_api_model::{SonarNotification, SonarUser, SonarUserUpdate};
+use sonar_client::SonarClient;
+
+// The user is created, reactivated, updated or deactivated.
+pub struct UserResource {
+    user: SonarUser,
+}
+
+impl UserResource {
+    pub fn new(user: SonarUser) -> UserResource {
+        UserResource { user }
+    }
+
+    fn describe_identity(user: &SonarUser) -> String {
+        if user.is_local() {
+            return "SonarQube".to_string();
+        }
+
+        return format!("[{}] as [{}]", user.external_provider.as_deref().unwrap_or_default(), user.external_identity.as_deref().unwrap_or(&user.login));
+    }
+
+    fn creation(&self, description: &str) -> Vec<Change> {
+        let user = self.user.clone();
+        let mut changes = vec![Change::new(Action::Created, description.to_string(), move |client| {
+            if user.is_local() && user.password.is_none() {
+                return Err(SonarApiError::new(format!("The password of the local user [{}] must be specified to create it", user.login)));
+            }
+
+            return client.create_user(&user);
+        })];
+
+        if !self.user.is_local() {
+            let user = self.user.clone();
+
+            changes.push(Change::new(Action::Updated, format!("is authenticated by {}", UserResource::describe_identity(&user)), move |client| client.update_identity_provider(&user)));
+        }
+
+        return changes;
+    }
+
+    fn update(&self, current_user: &SonarUser) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        if !self.user.has_same_identity(current_user) {
+            let description = format!("authenticated by {}, expecting {}", UserResource::describe_identity(current_user), UserResource::describe_identity(&self.user));
+            let user = self.user.clone();
+            let current_provider = current_user.external_provider.clone().unwrap_or_default();
+
+            changes.push(Change::new(Action::Updated, description, move |client| {
+                if user.is_local() {
+                    return Err(SonarApiError::new(format!("The user [{}] is authenticated by [{}], it cannot be converted to a local user", user.login, current_provider)));
+                }
+
+                return client.update_identity_provider(&user);
+            }));
+        }
+
+        let user = &self.user;
+        let mut descriptions = Vec::new();
+        let mut update = SonarUserUpdate { login: user.login.to_string(), ..SonarUserUpdate::default() };
+
+        if current_user.name != user.name {
+            descriptions.push(format!("name is [{}], expecting [{}]", current_user.name, user.name));
+            update.name = Some(user.name.to_string());
+        }
+
+        if !user.has_same_email(current_user) {
+            descriptions.push(format!("email is [{}], expecting [{}]", current_user.email.as_deref().unwrap_or_default(), user.email.as_deref().unwrap_or_default()));
+            update.email = user.email.clone();
+        }
+
+        if !user.has_same_scm_accounts(current_user) {
+            descriptions.push(format!("SCM accounts are {:?}, expecting {:?}", current_user.scm_accounts.as_deref().unwrap_or_default(), user.scm_accounts.as_deref().unwrap_or_default()));
+            update.scm_accounts = user.scm_accounts.clone();
+        }
+
+        if !descriptions.is_empty() {
+            changes.push(Change::new(Action::Updated, descriptions.join(", "), move |client| client.update_user(&update)));
+        }
+
+        // the current password cannot be compared, it's always changed unless it's only set at creation
+        if let Some(password) = &user.password {
+            if user.is_local() && user.password_policy == PasswordPolicy::Always {
+                let login = user.login.to_string();
+                let password = password.clone();
+
+                changes.push(Change::blind(Action::Updated, "password is changed".to_string(), move |client| client.change_user_password(&login, &password)));
+            }
+        }
+
+        return changes;
+    }
+}
+
+impl Resource for UserResource {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::User
+    }
+
+    fn name(&self) -> String {
+        self.user.login.to_string()
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let user = &self.user;
+
+        if user.login == ADMIN_USER && !user.groups.is_empty() {
+            return Err(SonarApiError::new("Cannot specify groups of user admin".to_string()));
+        }
+
+        if !user.is_local() && user.external_provider.is_none() {
+            return Err(SonarApiError::new(format!("The external provider of the user [{}] must be specified", user.login)));
+        }
+
+        if !user.is_active() && user.login == ADMIN_USER {
+            return Err(SonarApiError::new("Cannot deactivate user admin".to_string()));
+        }
+
+        let current_user = client.get_user(&user.login)?;
+
+        if !user.is_active() {
+            if !current_user.is_some_and(|current_user| current_user.is_active()) {
+                return Ok(vec![]);
+            }
+
+            let login = user.login.to_string();
+            let anonymize = user.anonymize;
+
+            return Ok(vec![Change::new(Action::Deleted, "is active, expecting deactivated".to_string(), move |client| client.deactivate_user(&login, anonymize))]);
+        }
+
+        match current_user {
+            None => {
+                return Ok(self.creation("does not exist"));
+            }
+            Some(ref current_user) if !current_user.is_active() => {
+                // a deactivated user is reactivated by creating it again
+                return Ok(self.creation("is deactivated"));
+            }
+            Some(current_user) => {
+                return Ok(self.update(&current_user));
+            }
+        }
+    }
+}
+
+// Notifications the user is subscribed to, the other ones are unsubscribed.
+pub struct UserNotifications {
+    login: String,
+    notifications: Vec<SonarNotification>,
+}
+
+impl UserNotifications {
+    pub fn new(login: &str, notifications: &[SonarNotification]) -> UserNotifications {
+        UserNotifications { login: login.to_string(), notifications: notifications.to_vec() }
+    }
+}
+
+impl Resource for UserNotifications {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::Notification
+    }
+
+    fn name(&self) -> String {
+        self.login.to_string()
+    }
+
+    fn depends_on(&self) -> Vec<(ResourceKind, String)> {
+        return vec![(ResourceKind::User, self.login.to_string())];
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let diff = MembershipDiff::between(&self.notifications, &client.get_user_notifications(&self.login)?);
+        let mut changes = Vec::new();
+
+        for notification in diff.to_remove {
+            let login = self.login.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("notification [{}] is not expected", notification), move |client| client.remove_user_notification(&login, &notification)));
+        }
+
+        for notification in diff.to_add {
+            let login = self.login.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("notification [{}] is missing", notification), move |client| client.add_user_notification(&login, &notification)));
+        }
+
+        return Ok(changes);
+    }
+}
+
+// Groups the user is member of, unmanaged groups (e.g. the default group) are not touched.
+pub struct UserGroups {
+    login: String,
+    groups: Vec<String>,
+    unmanaged: Vec<String>,
+}
+
+impl UserGroups {
+    pub fn new(login: &str, groups: &[String], unmanaged: &[&str]) -> UserGroups {
+        UserGroups { login: login.to_string(), groups: groups.to_vec(), unmanaged: unmanaged.iter().map(|group| group.to_string()).collect() }
+    }
+}
+
+impl Resource for UserGroups {
+    fn kind(&self) -> ResourceKind {
+        ResourceKind::Membership
+    }
+
+    fn name(&self) -> String {
+        self.login.to_string()
+    }
+
+    // the user and the groups, built-in groups exist without being declared
+    fn depends_on(&self) -> Vec<(ResourceKind, String)> {
+        let groups = self.groups.iter()
+            .filter(|group| !is_built_in_group(group))
+            .map(|group| (ResourceKind::Group, group.to_string()));
+
+        return std::iter::once((ResourceKind::User, self.login.to_string())).chain(groups).collect();
+    }
+
+    fn diff(&self, client: &dyn SonarClient) -> Result<Vec<Change>, SonarApiError> {
+        let unmanaged: Vec<&str> = self.unmanaged.iter().map(String::as_str).collect();
+        let diff = MembershipDiff::compute(&self.groups, &client.get_user_groups(&self.login)?, &unmanaged);
+        let mut changes = Vec::new();
+
+        for group in diff.to_remove {
+            let login = self.login.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("group [{}] is not expected", group), move |client| client.remove_user_from_group(&login, &group)));
+        }
+
+        for group in diff.to_add {
+            let login = self.login.to_string();
+
+            changes.push(Change::new(Action::Updated, format!("group [{}] is missing", group), move |client| client.add_user_to_group(&login, &group)));
+        }
+
+        return Ok(changes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml;
+
+    use super::UserResource;
+    use config_file_model::User;
+    use in_memory_sonar::InMemorySonar;
+    use report::Action;
+    use resource::Resource;
+    use sonar_api_model::SonarUser;
+    use sonar_client::SonarClient;
+
+    fn user(declaration: &str) -> UserResource {
+        let user: User = serde_yaml::from_str(declaration).unwrap();
+
+        return UserResource::new(SonarUser::from_configuration_user(&user, &|value| value.to_string()));
+    }
+
+    // Applies the user and returns the action of every change and the writes done.
+    fn apply(sonar: &InMemorySonar, declaration: &str) -> Result<(Vec<Action>, Vec<String>), String> {
+        let before = sonar.writes().len();
+        let changes = user(declaration).diff(sonar).map_err(|error| error.to_string())?;
+
+        for change in &changes {
+            change.apply(sonar).map_err(|error| error.to_string())?;
+        }
+
+        return Ok((changes.iter().map(|change| change.action).collect(), sonar.writes().split_off(before)));
+    }
+
+    const JDOE: &str = "{login: jdoe, name: John Doe, email: jdoe@example.com, scmAccounts: [jdoe], password: secret, passwordPolicy: createOnly}";
+
+    #[test]
+    fn creates_local_users() {
+        let sonar = InMemorySonar::new();
+
+        assert_eq!(apply(&sonar, JDOE).unwrap(), (vec![Action::Created], vec!["create user jdoe".to_string()]));
+        assert_eq!(apply(&sonar, JDOE).unwrap(), (vec![], vec![]));
+    }
+
+    #[test]
+    fn requires_the_password_of_new_local_users() {
+        let sonar = InMemorySonar::new();
+
+        assert_eq!(apply(&sonar, "{login: jdoe, name: John Doe}"), Err("The password of the local user [jdoe] must be specified to create it".to_string()));
+    }
+
+    #[test]
+    fn changes_the_password_unless_it_is_only_set_at_creation() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, JDOE).unwrap();
+
+        let (actions, writes) = apply(&sonar, &JDOE.replace("createOnly", "always")).unwrap();
+
+        assert_eq!(actions, vec![Action::Updated]);
+        assert_eq!(writes, vec!["change password of jdoe"]);
+    }
+
+    #[test]
+    fn deactivates_and_anonymizes_users() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, JDOE).unwrap();
+
+        let deactivated = JDOE.replace("passwordPolicy: createOnly", "state: deactivated, anonymize: true");
+
+        assert_eq!(apply(&sonar, &deactivated).unwrap(), (vec![Action::Deleted], vec!["deactivate user jdoe (anonymized)".to_string()]));
+        assert_eq!(apply(&sonar, &deactivated).unwrap(), (vec![], vec![]));
+
+        let user = sonar.get_user("jdoe").unwrap().unwrap();
+        assert!(!user.is_active());
+        assert_eq!((user.name.as_str(), user.email, user.scm_accounts), ("jdoe", None, None));
+    }
+
+    #[test]
+    fn does_not_create_users_to_deactivate() {
+        let sonar = InMemorySonar::new();
+
+        assert_eq!(apply(&sonar, "{login: jdoe, name: John Doe, state: deactivated}").unwrap(), (vec![], vec![]));
+    }
+
+    #[test]
+    fn reactivates_deactivated_users() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, JDOE).unwrap();
+        apply(&sonar, &JDOE.replace("passwordPolicy: createOnly", "state: deactivated")).unwrap();
+
+        let changes = user(JDOE).diff(&sonar).unwrap();
+        assert_eq!(changes.iter().map(|change| change.description.as_str()).collect::<Vec<_>>(), vec!["is deactivated"]);
+
+        assert_eq!(apply(&sonar, JDOE).unwrap(), (vec![Action::Created], vec!["create user jdoe".to_string()]));
+        assert!(sonar.get_user("jdoe").unwrap().is_some_and(|user| user.is_active()));
+    }
+
+    #[test]
+    fn cannot_convert_external_users_to_local_ones() {
+        let sonar = InMemorySonar::new();
+        apply(&sonar, "{login: jdoe, name: John Doe, local: false, externalProvider: saml}").unwrap();
+
+        assert_eq!(apply(&sonar, JDOE), Err("The user [jdoe] is authenticated by [saml], it cannot be converted to a local user".to_string()));
+    }
+}
@@ -0,0 +1,222 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use structopt::StructOpt;
+
+use configuration_options::ConfigurationOptions;
+use report_options::ReportOptions;
+use sonar_as_code::config_file_model::ConfigurationFile;
+use sonar_as_code::quality_profile::QualityProfileBackup;
+use sonar_as_code::report::{Action, Report, ResourceKind};
+use sonar_as_code::sonar_api::{is_built_in_group, ADMIN_USER, RULE_SEVERITIES, RULE_TYPES, TEMPLATE_PERMISSIONS};
+use sonar_as_code::sonar_api_model::{SonarCustomRule, SonarGroupCreationRequest, SonarProperty, SonarUser};
+use sonar_as_code::variable_resolver::VariableResolver;
+
+#[derive(StructOpt, Debug)]
+pub struct ValidateCmd {
+    #[structopt(flatten)]
+    configuration: ConfigurationOptions,
+
+    #[structopt(flatten)]
+    report: ReportOptions,
+}
+
+// Validates the configuration file without contacting SonarQube: variables must be resolved, keys must be unique,
+// permissions must exist and referenced groups must be declared.
+pub fn validate(cmd: ValidateCmd) -> Result<(), String> {
+    let config_file: ConfigurationFile = cmd.configuration.load()?;
+    let variable_resolver = VariableResolver::new(cmd.configuration.env_file.as_ref())?;
+
+    // variables that cannot be resolved are collected, they make the validation of the resource fail
+    let resolution_errors = RefCell::new(Vec::new());
+    let resolve_variables = |tokenized: &String| {
+        variable_resolver.try_resolve(tokenized).unwrap_or_else(|error| {
+            resolution_errors.borrow_mut().push(error);
+            String::new()
+        })
+    };
+
+    let mut report = Report::new("validate");
+
+    let mut property_names = HashSet::new();
+    for (index, property) in config_file.properties.unwrap_or_default().iter().enumerate() {
+        let property = SonarProperty::from_configuration_property(property, &resolve_variables);
+        let name = if property.name.is_empty() { format!("#{}", index) } else { property.name.to_string() };
+
+        let unresolved: Vec<String> = resolution_errors.borrow_mut().drain(..).collect();
+
+        let _ = report.track::<String, _>(ResourceKind::Property, name, || {
+            check_not_empty("name", &property.name)?;
+            check_unique(&mut property_names, &property.name)?;
+            check_resolution(&unresolved)?;
+
+            Ok(Action::Validated)
+        });
+    }
+
+    let mut rule_keys = HashSet::new();
+    for (index, rule) in config_file.custom_rules.unwrap_or_default().iter().enumerate() {
+        let rule = SonarCustomRule::from_configuration_custom_rule(rule, &resolve_variables);
+        let name = if rule.custom_key.is_empty() { format!("#{}", index) } else { rule.key() };
+
+        let unresolved: Vec<String> = resolution_errors.borrow_mut().drain(..).collect();
+
+        let _ = report.track::<String, _>(ResourceKind::CustomRule, name, || {
+            check_not_empty("customKey", &rule.custom_key)?;
+            check_resolution(&unresolved)?;
+            check_not_empty("name", &rule.name)?;
+            check_not_empty("markdownDescription", &rule.markdown_description)?;
+
+            // the repository of the rule is the one of the template
+            if rule.template_key.split(':').nth(1).is_none_or(str::is_empty) {
+                return Err(format!("The template key [{}] must be prefixed by its repository, e.g. java:S124", rule.template_key));
+            }
+            check_unique(&mut rule_keys, &rule.key())?;
+
+            if let Some(severity) = rule.severity.as_ref().filter(|severity| !RULE_SEVERITIES.contains(&severity.as_str())) {
+                return Err(format!("Unknown severity [{}], expecting one of {}", severity, RULE_SEVERITIES.join(", ")));
+            }
+
+            if let Some(rule_type) = rule.rule_type.as_ref().filter(|rule_type| !RULE_TYPES.contains(&rule_type.as_str())) {
+                return Err(format!("Unknown type [{}], expecting one of {}", rule_type, RULE_TYPES.join(", ")));
+            }
+
+            Ok(Action::Validated)
+        });
+    }
+
+    let mut profile_identities = HashSet::new();
+    for (index, profile) in config_file.quality_profiles.unwrap_or_default().iter().enumerate() {
+        let file = resolve_variables(&profile.file);
+        let unresolved: Vec<String> = resolution_errors.borrow_mut().drain(..).collect();
+        let backup = QualityProfileBackup::read(&file);
+        let name = match &backup {
+            Ok(backup) => format!("{} ({})", backup.name, backup.language),
+            Err(_) => if file.is_empty() { format!("#{}", index) } else { file.to_string() },
+        };
+
+        let _ = report.track::<String, _>(ResourceKind::QualityProfile, name.to_string(), || {
+            check_not_empty("file", &file)?;
+            check_resolution(&unresolved)?;
+            backup?;
+            check_unique(&mut profile_identities, &name)?;
+
+            Ok(Action::Validated)
+        });
+    }
+
+    let mut group_names = HashSet::new();
+    for (index, group) in config_file.groups.unwrap_or_default().iter().enumerate() {
+        let group = SonarGroupCreationRequest::from_configuration_group(group, &resolve_variables);
+        let name = if group.name.is_empty() { format!("#{}", index) } else { group.name.to_string() };
+
+        let unresolved: Vec<String> = resolution_errors.borrow_mut().drain(..).collect();
+
+        let _ = report.track::<String, _>(ResourceKind::Group, name, || {
+            check_not_empty("name", &group.name)?;
+            check_unique(&mut group_names, &group.name)?;
+            check_resolution(&unresolved)?;
+
+            for permission in &group.permissions {
+                if !TEMPLATE_PERMISSIONS.contains(&permission.as_str()) {
+                    return Err(format!("Unknown permission [{}], expecting one of {}", permission, TEMPLATE_PERMISSIONS.join(", ")));
+                }
+            }
+
+            for member in group.members.as_ref().unwrap_or(&Vec::new()) {
+                check_not_empty("member login", member)?;
+
+                if member == ADMIN_USER {
+                    return Err("Cannot specify user admin as member".to_string());
+                }
+            }
+
+            Ok(Action::Validated)
+        });
+    }
+
+    let mut user_logins = HashSet::new();
+    for (index, user) in config_file.users.unwrap_or_default().iter().enumerate() {
+        let user = SonarUser::from_configuration_user(user, &resolve_variables);
+        let name = if user.login.is_empty() { format!("#{}", index) } else { user.login.to_string() };
+
+        let unresolved: Vec<String> = resolution_errors.borrow_mut().drain(..).collect();
+
+        let _ = report.track::<String, _>(ResourceKind::User, name, || {
+            check_not_empty("login", &user.login)?;
+            check_unique(&mut user_logins, &user.login)?;
+            check_resolution(&unresolved)?;
+            check_not_empty("name", &user.name)?;
+
+            if user.login == ADMIN_USER && !user.groups.is_empty() {
+                return Err("Cannot specify groups of user admin".to_string());
+            }
+
+            // groups are applied before their members, they must be declared
+            for group in &user.groups {
+                if !is_built_in_group(group) && !group_names.contains(group) {
+                    return Err(format!("The group [{}] is not declared", group));
+                }
+            }
+
+            for notification in user.notifications.as_ref().unwrap_or(&Vec::new()) {
+                check_not_empty("notification type", &notification.notification_type)?;
+            }
+
+            if user.login == ADMIN_USER && !user.is_active() {
+                return Err("Cannot deactivate user admin".to_string());
+            }
+
+            if user.anonymize && user.is_active() {
+                return Err("Cannot anonymize an active user".to_string());
+            }
+
+            if user.is_local() {
+                if user.external_provider.is_some() || user.external_identity.is_some() {
+                    return Err("Cannot specify the external identity of a local user".to_string());
+                }
+            } else {
+                check_not_empty("externalProvider", user.external_provider.as_deref().unwrap_or_default())?;
+
+                if user.password.is_some() {
+                    return Err("Cannot specify the password of a non-local user".to_string());
+                }
+            }
+
+            Ok(Action::Validated)
+        });
+    }
+
+    cmd.report.publish(&report)?;
+
+    let failed = report.summary().failed;
+    if failed > 0 {
+        return Err(format!("The configuration is invalid, {} resource(s) failed the validation", failed));
+    }
+
+    return Ok(());
+}
+
+fn check_resolution(unresolved: &[String]) -> Result<(), String> {
+    if !unresolved.is_empty() {
+        return Err(unresolved.join(" "));
+    }
+
+    return Ok(());
+}
+
+fn check_not_empty(field: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("The {} must not be empty", field));
+    }
+
+    return Ok(());
+}
+
+fn check_unique(keys: &mut HashSet<String>, key: &str) -> Result<(), String> {
+    if !keys.insert(key.to_string()) {
+        return Err(format!("[{}] is declared more than once", key));
+    }
+
+    return Ok(());
+}
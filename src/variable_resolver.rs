@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fs;
+
+const ENV_PREFIX: &str = "env:";
+const FILE_PREFIX: &str = "file:";
+const DEFAULT_SEPARATOR: &str = ":-";
+const REQUIRED_SEPARATOR: &str = ":?";
+
+// Resolves variables of the configuration file:
+// * ${VAR} and ${env:VAR}: value of the environment variable (or of the .env file),
+// * ${file:/run/secrets/x}: content of the file (without the trailing new line),
+// * ${VAR:-default}: default value used when the variable is not defined or empty,
+// * ${VAR:?message}: fails with the message when the variable is not defined or empty,
+// * $${VAR}: the literal ${VAR}.
+pub struct VariableResolver {
+    variables: HashMap<String, String>,
+}
+
+impl VariableResolver {
+    pub fn new(env_file: Option<&String>) -> Result<VariableResolver, String> {
+        let mut variables = HashMap::new();
+
+        if let Some(env_file) = env_file {
+            debug!("Loading variables from [{}].", env_file);
+
+            variables.extend(parse_env_file(env_file)?);
+        }
+
+        // variables of the environment take precedence over the ones of the .env file
+        variables.extend(std::env::vars());
+
+        return Ok(VariableResolver { variables });
+    }
+
+    pub fn try_resolve(&self, tokenized: &str) -> Result<String, String> {
+        let mut resolved = String::new();
+        let mut remaining = tokenized;
+
+        while let Some(start) = remaining.find('$') {
+            resolved.push_str(&remaining[..start]);
+            remaining = &remaining[start..];
+
+            if remaining.starts_with("$${") {
+                resolved.push_str("${");
+                remaining = &remaining[3..];
+            } else if remaining.starts_with("${") {
+                let end = find_closing_brace(remaining)
+                    .ok_or_else(|| format!("Unterminated variable in [{}].", tokenized))?;
+
+                resolved.push_str(&self.resolve_expression(&remaining[2..end])?);
+                remaining = &remaining[end + 1..];
+            } else {
+                resolved.push('$');
+                remaining = &remaining[1..];
+            }
+        }
+
+        resolved.push_str(remaining);
+
+        return Ok(resolved);
+    }
+
+    fn resolve_expression(&self, expression: &str) -> Result<String, String> {
+        let (name, modifier) = match (expression.find(DEFAULT_SEPARATOR), expression.find(REQUIRED_SEPARATOR)) {
+            (Some(default), Some(required)) if required < default => (&expression[..required], Some(&expression[required..])),
+            (Some(default), _) => (&expression[..default], Some(&expression[default..])),
+            (None, Some(required)) => (&expression[..required], Some(&expression[required..])),
+            (None, None) => (expression, None),
+        };
+
+        // a file that cannot be read is reported as is, unless there is a default value or a message
+        let value = if let Some(path) = name.strip_prefix(FILE_PREFIX) {
+            read_secret_file(path)
+        } else {
+            let name = name.strip_prefix(ENV_PREFIX).unwrap_or(name);
+
+            if name.is_empty() {
+                return Err(format!("Missing variable name in [${{{}}}].", expression));
+            }
+
+            self.variables.get(name).cloned().ok_or_else(|| format!("Variable [{}] is not defined.", name))
+        }.and_then(|value| if value.is_empty() { Err(format!("Variable [{}] is empty.", name)) } else { Ok(value) });
+
+        match (value, modifier) {
+            (Ok(value), _) => Ok(value),
+            (Err(_), Some(modifier)) if modifier.starts_with(DEFAULT_SEPARATOR) => self.try_resolve(&modifier[DEFAULT_SEPARATOR.len()..]),
+            (Err(_), Some(modifier)) => Err(format!("Variable [{}] is required: {}", name, &modifier[REQUIRED_SEPARATOR.len()..])),
+            (Err(error), None) => Err(error),
+        }
+    }
+}
+
+// Returns the position of the brace closing the variable starting at the beginning of the string (nested variables are skipped).
+fn find_closing_brace(value: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (index, character) in value.char_indices() {
+        match character {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    return None;
+}
+
+fn read_secret_file(path: &str) -> Result<String, String> {
+    return fs::read_to_string(path)
+        .map(|content| content.trim_end_matches(&['\n', '\r'][..]).to_string())
+        .map_err(|error| format!("Cannot read the secret file [{}]: {}.", path, error));
+}
+
+fn parse_env_file(path: &str) -> Result<HashMap<String, String>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| format!("Cannot read the env file [{}]: {}", path, error))?;
+
+    let mut variables = HashMap::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let separator = line.find('=')
+            .ok_or_else(|| format!("Invalid line {} in the env file [{}], expecting KEY=VALUE", index + 1, path))?;
+
+        let value = line[separator + 1..].trim();
+        let value = if value.len() >= 2 && ((value.starts_with('"') && value.ends_with('"')) || (value.starts_with('\'') && value.ends_with('\''))) {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+
+        variables.insert(line[..separator].trim().to_string(), value.to_string());
+    }
+
+    return Ok(variables);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    fn resolver(variables: &[(&str, &str)]) -> VariableResolver {
+        VariableResolver { variables: variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect() }
+    }
+
+    fn temp_file(name: &str, content: &str) -> String {
+        let file = env::temp_dir().join(format!("sonar-as-code-{}-{}", name, std::process::id()));
+        fs::write(&file, content).unwrap();
+
+        return file.to_string_lossy().to_string();
+    }
+
+    #[test]
+    fn resolves_variables() {
+        let resolver = resolver(&[("HOST", "sonar"), ("PORT", "9000")]);
+
+        assert_eq!(resolver.try_resolve("https://${HOST}:${env:PORT}/"), Ok("https://sonar:9000/".to_string()));
+        assert_eq!(resolver.try_resolve("no variable"), Ok("no variable".to_string()));
+        assert_eq!(resolver.try_resolve("costs 5$"), Ok("costs 5$".to_string()));
+    }
+
+    #[test]
+    fn resolves_files() {
+        let file = temp_file("secret", "s3cr3t\n");
+
+        assert_eq!(resolver(&[]).try_resolve(&format!("${{file:{}}}", file)), Ok("s3cr3t".to_string()));
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn reports_files_that_cannot_be_read() {
+        let error = resolver(&[]).try_resolve("${file:/missing/secret}").unwrap_err();
+
+        assert!(error.starts_with("Cannot read the secret file [/missing/secret]: "), "{}", error);
+        assert_eq!(resolver(&[]).try_resolve("${file:/missing/secret:-none}"), Ok("none".to_string()));
+    }
+
+    #[test]
+    fn uses_the_default_value_when_the_variable_is_not_defined_or_empty() {
+        let resolver = resolver(&[("EMPTY", ""), ("HOST", "sonar")]);
+
+        assert_eq!(resolver.try_resolve("${MISSING:-localhost}"), Ok("localhost".to_string()));
+        assert_eq!(resolver.try_resolve("${EMPTY:-localhost}"), Ok("localhost".to_string()));
+        assert_eq!(resolver.try_resolve("${HOST:-localhost}"), Ok("sonar".to_string()));
+        assert_eq!(resolver.try_resolve("${MISSING:-${HOST}}"), Ok("sonar".to_string()));
+    }
+
+    #[test]
+    fn fails_with_the_message_when_a_required_variable_is_not_defined() {
+        let resolver = resolver(&[("HOST", "sonar")]);
+
+        assert_eq!(resolver.try_resolve("${HOST:?the host is required}"), Ok("sonar".to_string()));
+        assert_eq!(resolver.try_resolve("${TOKEN:?generate one with make token:-admin}"), Err("Variable [TOKEN] is required: generate one with make token:-admin".to_string()));
+    }
+
+    #[test]
+    fn fails_when_a_variable_is_not_defined() {
+        assert_eq!(resolver(&[]).try_resolve("${MISSING}"), Err("Variable [MISSING] is not defined.".to_string()));
+        assert_eq!(resolver(&[("EMPTY", "")]).try_resolve("${EMPTY}"), Err("Variable [EMPTY] is empty.".to_string()));
+        assert_eq!(resolver(&[]).try_resolve("${env:}"), Err("Missing variable name in [${env:}].".to_string()));
+    }
+
+    #[test]
+    fn escapes_variables() {
+        assert_eq!(resolver(&[("HOST", "sonar")]).try_resolve("$${HOST} is ${HOST}"), Ok("${HOST} is sonar".to_string()));
+    }
+
+    #[test]
+    fn fails_on_unterminated_variables() {
+        assert_eq!(resolver(&[("HOST", "sonar")]).try_resolve("https://${HOST"), Err("Unterminated variable in [https://${HOST].".to_string()));
+    }
+
+    #[test]
+    fn parses_env_files() {
+        let file = temp_file("env", "# comment\n\nHOST=sonar\nexport PORT = 9000\nQUOTED=\"a b\"\nSINGLE='c d'\n");
+
+        let variables = parse_env_file(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(variables.len(), 4);
+        assert_eq!(variables["HOST"], "sonar");
+        assert_eq!(variables["PORT"], "9000");
+        assert_eq!(variables["QUOTED"], "a b");
+        assert_eq!(variables["SINGLE"], "c d");
+    }
+
+    #[test]
+    fn rejects_invalid_env_files() {
+        let file = temp_file("invalid-env", "HOST=sonar\nPORT\n");
+
+        let error = parse_env_file(&file).unwrap_err();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(error, format!("Invalid line 2 in the env file [{}], expecting KEY=VALUE", file));
+    }
+
+    #[test]
+    fn environment_variables_take_precedence_over_the_env_file() {
+        let file = temp_file("precedence-env", "SONAR_AS_CODE_TEST_HOST=from-file\nSONAR_AS_CODE_TEST_PORT=9000\n");
+        env::set_var("SONAR_AS_CODE_TEST_HOST", "from-environment");
+
+        let resolver = VariableResolver::new(Some(&file)).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(resolver.try_resolve("${SONAR_AS_CODE_TEST_HOST}:${SONAR_AS_CODE_TEST_PORT}"), Ok("from-environment:9000".to_string()));
+    }
+}
@@ -0,0 +1,68 @@
+#![allow(clippy::needless_return)]
+
+extern crate reqwest;
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+extern crate sonar_as_code;
+extern crate tiny_http;
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::{quality_profile, run, setup, stderr, stdout, MockSonar};
+use sonar_as_code::sonar_client::SonarClient;
+
+const CONFIGURATION: &str = r#"
+properties:
+  - name: sonar.core.serverBaseURL
+    value: https://sonar.example.com
+groups:
+  - name: developers
+    description: Developers
+    permissions: [codeviewer, scan, user]
+users:
+  - login: jdoe
+    name: John Doe
+    local: false
+    externalProvider: saml
+    email: jdoe@example.com
+    groups: [developers]
+"#;
+
+fn backup(sonar: &MockSonar, directory: &Path) -> PathBuf {
+    let output = run(&["backup", "-d", directory.to_str().unwrap(), "-s", sonar.url(), "-a", "1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    return PathBuf::from(stdout(&output).trim());
+}
+
+#[test]
+fn backs_up_and_restores_on_another_server() {
+    let source = MockSonar::start();
+    assert!(setup(&source, CONFIGURATION, &[]).status.success());
+    source.sonar().restore_quality_profile(&quality_profile("Strict", "java")).unwrap();
+
+    let directory = env::temp_dir().join(format!("sonar-as-code-backups-{}", std::process::id()));
+    let backup_directory = backup(&source, &directory);
+
+    // built-in quality profiles are not backed up
+    let profiles: Vec<String> = fs::read_dir(backup_directory.join("quality-profiles")).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(profiles, vec!["java-Strict.xml"]);
+
+    let target = MockSonar::start();
+    let output = run(&["restore", "-d", backup_directory.to_str().unwrap(), "-s", target.url(), "-a", "1"]);
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(target.property("sonar.core.serverBaseURL").as_deref(), Some("https://sonar.example.com"));
+    assert_eq!(target.template_permissions("developers"), vec!["codeviewer", "scan", "user"]);
+    assert_eq!(target.user_groups("jdoe"), vec!["developers", "sonar-users"]);
+    assert_eq!(target.sonar().get_user("jdoe").unwrap().unwrap().external_provider.as_deref(), Some("saml"));
+    assert_eq!(target.sonar().backup_quality_profile("java", "Strict").unwrap(), quality_profile("Strict", "java"));
+}
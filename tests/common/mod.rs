@@ -0,0 +1,582 @@
+// Not every test uses every helper.
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::Value;
+use sonar_as_code::in_memory_sonar::InMemorySonar;
+use sonar_as_code::secret_string::SecretString;
+use sonar_as_code::sonar_api::SonarApiError;
+use sonar_as_code::sonar_api_model::{SonarCustomRule, SonarGroup, SonarGroupCreationRequest, SonarGroupMembership, SonarNotification, SonarProperty, SonarUser, SonarUserMembership, SonarUserUpdate};
+use sonar_as_code::sonar_client::SonarClient;
+use tiny_http::{Header, Request, Response, Server};
+
+// Rule templates of a fresh SonarQube used by the tests, the in-memory SonarQube does not know them.
+const RULE_TEMPLATES: [&str; 1] = ["java:S124"];
+
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+// Request received by the stand-in, parameters come from the query string and the form-encoded body.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl RecordedRequest {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    fn params(&self, name: &str) -> Vec<String> {
+        self.params.iter().filter(|(key, _)| key == name).map(|(_, value)| value.to_string()).collect()
+    }
+
+    fn param_or_empty(&self, name: &str) -> String {
+        self.param(name).unwrap_or_default().to_string()
+    }
+}
+
+// What only exists on the wire, the state of SonarQube is kept by the in-memory SonarQube.
+struct WireState {
+    requests: Vec<RecordedRequest>,
+    // items returned per page, whatever the requested page size
+    page_size: usize,
+    // status and error message answered instead of handling the request
+    failures: HashMap<String, (u16, String)>,
+    // password of the administrator, checked when it's changed
+    admin_password: String,
+    // login and name of the generated tokens
+    tokens: Vec<(String, String)>,
+}
+
+// Local HTTP stand-in translating the web API of SonarQube used by the command line to the in-memory SonarQube.
+pub struct MockSonar {
+    url: String,
+    sonar: Arc<InMemorySonar>,
+    wire: Arc<Mutex<WireState>>,
+    server: Arc<Server>,
+}
+
+impl MockSonar {
+    pub fn start() -> MockSonar {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}/", server.server_addr().to_ip().unwrap());
+        let sonar = Arc::new(InMemorySonar::new());
+        let wire = Arc::new(Mutex::new(WireState {
+            requests: Vec::new(),
+            page_size: 500,
+            failures: HashMap::new(),
+            admin_password: "admin".to_string(),
+            tokens: Vec::new(),
+        }));
+
+        let thread_server = server.clone();
+        let thread_sonar = sonar.clone();
+        let thread_wire = wire.clone();
+        thread::spawn(move || {
+            for request in thread_server.incoming_requests() {
+                handle(&thread_sonar, &thread_wire, request);
+            }
+        });
+
+        return MockSonar { url, sonar, wire, server };
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // SonarQube behind the stand-in, to prepare or check its state.
+    pub fn sonar(&self) -> &InMemorySonar {
+        &self.sonar
+    }
+
+    fn wire(&self) -> MutexGuard<'_, WireState> {
+        self.wire.lock().unwrap()
+    }
+
+    // Smaller pages than the ones requested, to exercise the pagination.
+    pub fn set_page_size(&self, page_size: usize) {
+        self.wire().page_size = page_size;
+    }
+
+    pub fn fail(&self, path: &str, status: u16, message: &str) {
+        self.wire().failures.insert(path.to_string(), (status, message.to_string()));
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.wire().requests.clone()
+    }
+
+    // Requests that change the state, in order.
+    pub fn writes(&self) -> Vec<RecordedRequest> {
+        self.requests().into_iter().filter(|request| request.method == "POST").collect()
+    }
+
+    pub fn clear_requests(&self) {
+        self.wire().requests.clear();
+    }
+
+    pub fn tokens(&self) -> Vec<(String, String)> {
+        self.wire().tokens.clone()
+    }
+
+    pub fn add_user(&self, login: &str, local: bool) {
+        let user: SonarUser = serde_json::from_value(json!({"login": login, "name": login, "groups": [], "local": local})).unwrap();
+
+        self.sonar.create_user(&SonarUser { password: Some(SecretString::secret("secret".to_string())), ..user }).unwrap();
+    }
+
+    pub fn property(&self, key: &str) -> Option<String> {
+        self.sonar.get_property_value(key).unwrap()
+    }
+
+    pub fn user_groups(&self, login: &str) -> Vec<String> {
+        self.sonar.get_user_groups(login).unwrap()
+    }
+
+    pub fn template_permissions(&self, group: &str) -> Vec<String> {
+        self.sonar.get_template_groups().unwrap().into_iter()
+            .filter(|template_group| template_group.name == group)
+            .flat_map(|template_group| template_group.permissions)
+            .collect()
+    }
+}
+
+impl Drop for MockSonar {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+impl WireState {
+    // Items of the requested page, with the paging of SonarQube.
+    fn page<T: Serialize>(&self, request: &RecordedRequest, items: Vec<T>) -> (Value, Vec<Value>) {
+        let index: usize = request.param("p").unwrap_or("1").parse().unwrap();
+        let size: usize = request.param("ps").unwrap_or("25").parse::<usize>().unwrap().min(self.page_size);
+
+        let page_items = items.iter().skip((index - 1) * size).take(size).map(|item| serde_json::to_value(item).unwrap()).collect();
+
+        return (json!({"pageIndex": index, "pageSize": size, "total": items.len()}), page_items);
+    }
+
+    fn handle(&mut self, sonar: &InMemorySonar, request: &RecordedRequest) -> Result<Value, (u16, String)> {
+        let param = |name: &str| request.param_or_empty(name);
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/api") => Ok(json!({})),
+            ("GET", "/api/system/status") => Ok(json!({"status": "UP"})),
+
+            ("GET", "/api/settings/values") => {
+                let keys = param("keys");
+                let multi_valued = sonar.get_multi_valued_properties().map_err(bad_request)?;
+
+                // every value is returned without keys, several values are returned as a list
+                let settings: Vec<Value> = sonar.get_property_values().map_err(bad_request)?.into_iter()
+                    .filter(|(key, _)| keys.is_empty() || keys.split(',').any(|requested| requested == key))
+                    .map(|(key, value)| match multi_valued.contains(&key) {
+                        true => json!({"key": key, "values": value.split(',').collect::<Vec<&str>>(), "inherited": false}),
+                        false => json!({"key": key, "value": value, "inherited": false}),
+                    })
+                    .collect();
+
+                return Ok(json!({"settings": settings}));
+            }
+            ("POST", "/api/settings/set") => {
+                sonar.set_property(&SonarProperty { name: param("key"), value: SecretString::plain(param("value")) }).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+
+            ("GET", "/api/user_groups/search") => {
+                let query = param("q");
+                let groups: Vec<SonarGroup> = sonar.get_groups().map_err(bad_request)?.into_iter().filter(|group| group.name.contains(&query)).collect();
+                let (paging, groups) = self.page(request, groups);
+
+                return Ok(json!({"paging": paging, "groups": groups}));
+            }
+            ("POST", "/api/user_groups/create") => {
+                let group_request = SonarGroupCreationRequest { name: param("name"), description: param("description"), permissions: vec![], members: None };
+                sonar.create_group(&group_request).map_err(bad_request)?;
+
+                return Ok(json!({"group": sonar.get_group_by_name(&group_request.name).map_err(bad_request)?}));
+            }
+            ("POST", "/api/user_groups/delete") => {
+                sonar.delete_group(&param("name")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("POST", "/api/user_groups/update") => {
+                let id: usize = param("id").parse().unwrap();
+                let group = match sonar.get_groups().map_err(bad_request)?.into_iter().find(|group| group.id == id) {
+                    Some(group) => group,
+                    None => return Err((404, format!("Could not find a user group with id '{}'.", id))),
+                };
+                sonar.update_group_description(&group, &param("description")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("GET", "/api/user_groups/users") => {
+                let mut members = Vec::new();
+                for login in sonar.get_group_members(&param("name")).map_err(bad_request)? {
+                    let name = sonar.get_user(&login).map_err(bad_request)?.map(|user| user.name).unwrap_or_default();
+                    members.push(SonarGroupMembership { name, login, selected: true });
+                }
+                let (paging, members) = self.page(request, members);
+
+                return Ok(json!({"p": paging["pageIndex"], "ps": paging["pageSize"], "total": paging["total"], "users": members}));
+            }
+            ("POST", "/api/user_groups/add_user") => {
+                sonar.add_user_to_group(&param("login"), &param("name")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("POST", "/api/user_groups/remove_user") => {
+                sonar.remove_user_from_group(&param("login"), &param("name")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+
+            ("GET", "/api/permissions/template_groups") => {
+                let (paging, groups) = self.page(request, sonar.get_template_groups().map_err(bad_request)?);
+
+                return Ok(json!({"paging": paging, "groups": groups}));
+            }
+            ("POST", "/api/permissions/add_group_to_template") => {
+                sonar.add_permission_to_group(&param("groupName"), &param("permission")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("POST", "/api/permissions/remove_group_from_template") => {
+                sonar.remove_permission_to_group(&param("groupName"), &param("permission")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+
+            ("GET", "/api/users/search") => {
+                let query = param("q");
+                let deactivated = param("deactivated") == "true";
+
+                let users: Vec<SonarUser> = sonar.get_users().map_err(bad_request)?.into_iter()
+                    .filter(|user| user.login.contains(&query) && user.is_active() != deactivated)
+                    .collect();
+                let (paging, users) = self.page(request, users);
+
+                return Ok(json!({"paging": paging, "users": users}));
+            }
+            ("POST", "/api/users/create") => {
+                let user: SonarUser = serde_json::from_value(json!({
+                    "login": param("login"),
+                    "name": param("name"),
+                    "groups": [],
+                    "local": param("local") != "false",
+                    "email": request.param("email"),
+                    "scmAccounts": scm_accounts(request),
+                })).unwrap();
+                let password = request.param("password").map(|password| SecretString::secret(password.to_string()));
+                sonar.create_user(&SonarUser { password, ..user }).map_err(bad_request)?;
+
+                return Ok(json!({"user": sonar.get_user(&param("login")).map_err(bad_request)?}));
+            }
+            ("POST", "/api/users/update") => {
+                let update = SonarUserUpdate {
+                    login: param("login"),
+                    name: request.param("name").map(str::to_string),
+                    email: request.param("email").map(str::to_string),
+                    scm_accounts: scm_accounts(request),
+                };
+                sonar.update_user(&update).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("POST", "/api/users/update_identity_provider") => {
+                let user: SonarUser = serde_json::from_value(json!({
+                    "login": param("login"),
+                    "name": "",
+                    "groups": [],
+                    "externalProvider": param("newExternalProvider"),
+                    "externalIdentity": request.param("newExternalIdentity"),
+                })).unwrap();
+                sonar.update_identity_provider(&user).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("POST", "/api/users/change_password") => {
+                // only the administrator sends its current password
+                if let Some(previous_password) = request.param("previousPassword") {
+                    if previous_password != self.admin_password {
+                        return Err((400, "Incorrect password".to_string()));
+                    }
+                    self.admin_password = param("password");
+                }
+                sonar.change_user_password(&param("login"), &SecretString::secret(param("password"))).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("POST", "/api/users/deactivate") => {
+                sonar.deactivate_user(&param("login"), param("anonymize") == "true").map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("GET", "/api/users/groups") => {
+                let groups = sonar.get_groups().map_err(bad_request)?;
+                let memberships: Vec<SonarUserMembership> = sonar.get_user_groups(&param("login")).map_err(bad_request)?.into_iter()
+                    .filter_map(|name| groups.iter().find(|group| group.name == name))
+                    .map(|group| SonarUserMembership { id: group.id, name: group.name.to_string(), description: group.description.to_string(), selected: true, default: group.name == "sonar-users" })
+                    .collect();
+                let (paging, groups) = self.page(request, memberships);
+
+                return Ok(json!({"paging": paging, "groups": groups}));
+            }
+
+            ("GET", "/api/notifications/list") => {
+                let notifications = sonar.get_user_notifications(&param("login")).map_err(bad_request)?;
+
+                return Ok(json!({"channels": ["EmailNotificationChannel"], "notifications": notifications}));
+            }
+            ("POST", "/api/notifications/add") => {
+                sonar.add_user_notification(&param("login"), &notification(request)).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+            ("POST", "/api/notifications/remove") => {
+                sonar.remove_user_notification(&param("login"), &notification(request)).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+
+            ("POST", "/api/user_tokens/generate") => {
+                let token = (param("login"), param("name"));
+                if self.tokens.contains(&token) {
+                    return Err((400, format!("A user token for login '{}' and name '{}' already exists", token.0, token.1)));
+                }
+
+                self.tokens.push(token.clone());
+
+                return Ok(json!({"login": token.0, "name": token.1, "token": format!("squ_{}_{}", token.0, self.tokens.len())}));
+            }
+
+            ("GET", "/api/qualityprofiles/search") => {
+                return Ok(json!({"profiles": sonar.get_quality_profiles().map_err(bad_request)?}));
+            }
+            ("GET", "/api/qualityprofiles/backup") => {
+                return match sonar.backup_quality_profile(&param("language"), &param("qualityProfile")) {
+                    Ok(backup) => Ok(Value::String(backup)),
+                    Err(error) => Err((404, error.to_string())),
+                };
+            }
+            ("POST", "/api/qualityprofiles/restore") => {
+                sonar.restore_quality_profile(&param("backup")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+
+            ("GET", "/api/rules/show") => {
+                return match sonar.get_rule(&param("key")).map_err(bad_request)? {
+                    Some(rule) => Ok(json!({"rule": rule})),
+                    None => Err((404, format!("Rule not found: {}", param("key")))),
+                };
+            }
+            ("GET", "/api/rules/search") => {
+                let rules: Vec<Value> = match param("is_template") == "true" {
+                    true => RULE_TEMPLATES.iter().map(|key| json!({"key": key, "name": key, "isTemplate": true})).collect(),
+                    false => sonar.get_custom_rules().map_err(bad_request)?.into_iter()
+                        .filter(|rule| rule.template_key.as_deref() == Some(param("template_key").as_str()))
+                        .map(|rule| serde_json::to_value(rule).unwrap())
+                        .collect(),
+                };
+                let (paging, rules) = self.page(request, rules);
+
+                return Ok(json!({"total": paging["total"], "p": paging["pageIndex"], "ps": paging["pageSize"], "rules": rules}));
+            }
+            ("POST", "/api/rules/create") => {
+                if !RULE_TEMPLATES.contains(&param("template_key").as_str()) {
+                    return Err((400, format!("The template key doesn't exist: {}", param("template_key"))));
+                }
+
+                let rule = custom_rule(request, param("template_key"), param("custom_key"));
+                sonar.create_custom_rule(&rule).map_err(bad_request)?;
+
+                return Ok(json!({"rule": sonar.get_rule(&rule.key()).map_err(bad_request)?}));
+            }
+            ("POST", "/api/rules/update") => {
+                let key = param("key");
+                let template_key = match sonar.get_rule(&key).map_err(bad_request)? {
+                    Some(rule) => rule.template_key.unwrap_or_default(),
+                    None => return Err((404, format!("This rule does not exist: {}", key))),
+                };
+
+                let rule = custom_rule(request, template_key, key.split(':').nth(1).unwrap_or_default().to_string());
+                sonar.update_custom_rule(&rule).map_err(bad_request)?;
+
+                return Ok(json!({"rule": sonar.get_rule(&key).map_err(bad_request)?}));
+            }
+            ("POST", "/api/rules/delete") => {
+                sonar.delete_rule(&param("key")).map_err(bad_request)?;
+
+                return Ok(Value::Null);
+            }
+
+            _ => Err((404, format!("Unknown url : {}", request.path))),
+        }
+    }
+}
+
+fn bad_request(error: SonarApiError) -> (u16, String) {
+    (400, error.to_string())
+}
+
+// SCM accounts are repeated, a single empty one clears them.
+fn scm_accounts(request: &RecordedRequest) -> Option<Vec<String>> {
+    let accounts = request.params("scmAccount");
+    if accounts.is_empty() {
+        return None;
+    }
+
+    return Some(accounts.into_iter().filter(|account| !account.is_empty()).collect());
+}
+
+fn notification(request: &RecordedRequest) -> SonarNotification {
+    SonarNotification {
+        notification_type: request.param_or_empty("type"),
+        channel: request.param_or_empty("channel"),
+        project: request.param("project").map(str::to_string),
+    }
+}
+
+fn custom_rule(request: &RecordedRequest, template_key: String, custom_key: String) -> SonarCustomRule {
+    SonarCustomRule {
+        template_key,
+        custom_key,
+        name: request.param_or_empty("name"),
+        severity: request.param("severity").map(str::to_string),
+        rule_type: request.param("type").map(str::to_string),
+        markdown_description: request.param_or_empty("markdown_description"),
+        params: rule_params(&request.param_or_empty("params")),
+    }
+}
+
+fn handle(sonar: &InMemorySonar, wire: &Mutex<WireState>, mut request: Request) {
+    let url = Url::parse(&format!("http://localhost{}", request.url())).unwrap();
+    let mut params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body).unwrap();
+    let multipart = request.headers().iter().any(|header| header.field.equiv("Content-Type") && header.value.as_str().starts_with("multipart/form-data"));
+    if multipart {
+        params.extend(multipart_file(&body));
+    } else if !body.is_empty() {
+        params.extend(Url::parse(&format!("http://localhost/?{}", body)).unwrap().query_pairs().into_owned());
+    }
+
+    let recorded_request = RecordedRequest { method: request.method().to_string(), path: url.path().to_string(), params };
+
+    let result = {
+        let mut wire = wire.lock().unwrap();
+        wire.requests.push(recorded_request.clone());
+
+        match wire.failures.get(&recorded_request.path) {
+            Some(failure) => Err(failure.clone()),
+            None => wire.handle(sonar, &recorded_request),
+        }
+    };
+
+    let (status, body) = match result {
+        Ok(Value::Null) => (204, String::new()),
+        Ok(Value::String(text)) => (200, text),
+        Ok(body) => (200, body.to_string()),
+        Err((status, message)) => (status, json!({"errors": [{"msg": message}]}).to_string()),
+    };
+
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+    let _ = request.respond(response);
+}
+
+// Parameters of a rule sent as key=value pairs separated by semicolons, values containing one are quoted.
+fn rule_params(params: &str) -> BTreeMap<String, String> {
+    let mut pairs = vec![String::new()];
+    let mut quoted = false;
+    for character in params.chars() {
+        match character {
+            '"' => quoted = !quoted,
+            ';' if !quoted => pairs.push(String::new()),
+            _ => pairs.last_mut().unwrap().push(character),
+        }
+    }
+
+    return pairs.iter()
+        .filter_map(|pair| pair.find('=').map(|separator| (pair[..separator].to_string(), pair[separator + 1..].to_string())))
+        .collect();
+}
+
+// Name and content of the file uploaded in the multipart body.
+fn multipart_file(body: &str) -> Option<(String, String)> {
+    let name_start = body.find("name=\"")? + 6;
+    let name = &body[name_start..name_start + body[name_start..].find('"')?];
+    let content_start = name_start + body[name_start..].find("\r\n\r\n")? + 4;
+    let content_end = body.rfind("\r\n--")?;
+
+    return Some((name.to_string(), body[content_start..content_end].to_string()));
+}
+
+// XML backup of a quality profile without rules.
+pub fn quality_profile(name: &str, language: &str) -> String {
+    format!("<?xml version='1.0' encoding='UTF-8'?>\n<profile>\n  <name>{}</name>\n  <language>{}</language>\n  <rules/>\n</profile>\n", name, language)
+}
+
+// Writes the configuration in a temporary file, its path is returned.
+pub fn configuration_file(content: &str) -> PathBuf {
+    let file = env::temp_dir().join(format!("sonar-as-code-{}-{}.yaml", std::process::id(), NEXT_FILE.fetch_add(1, Ordering::SeqCst)));
+    fs::write(&file, content).unwrap();
+
+    return file;
+}
+
+// Runs the command line with the specified arguments.
+pub fn run(args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_sonar-as-code")).args(args).output().unwrap();
+}
+
+// Runs the command line with the specified arguments, the input is written to its standard input.
+pub fn run_with_input(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sonar-as-code")).args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    return child.wait_with_output().unwrap();
+}
+
+pub fn setup(sonar: &MockSonar, configuration: &str, extra_args: &[&str]) -> Output {
+    let file = configuration_file(configuration);
+
+    let mut args = vec!["setup", "-f", file.to_str().unwrap(), "-s", sonar.url(), "-a", "1"];
+    args.extend_from_slice(extra_args);
+
+    let output = run(&args);
+    fs::remove_file(file).unwrap();
+
+    return output;
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
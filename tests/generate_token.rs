@@ -0,0 +1,39 @@
+#![allow(clippy::needless_return)]
+
+extern crate reqwest;
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+extern crate sonar_as_code;
+extern crate tiny_http;
+
+mod common;
+
+use common::{run, stderr, stdout, MockSonar};
+
+fn generate_token(sonar: &MockSonar, name: &str) -> std::process::Output {
+    return run(&["generate-token", "-s", sonar.url(), "-a", "1", "-n", name]);
+}
+
+#[test]
+fn prints_the_generated_token() {
+    let sonar = MockSonar::start();
+
+    let output = generate_token(&sonar, "ci");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "squ_admin_1\n");
+    assert_eq!(sonar.tokens(), vec![("admin".to_string(), "ci".to_string())]);
+}
+
+#[test]
+fn fails_when_the_token_already_exists() {
+    let sonar = MockSonar::start();
+    assert!(generate_token(&sonar, "ci").status.success());
+
+    let output = generate_token(&sonar, "ci");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).is_empty());
+    assert!(stderr(&output).contains("A user token for login 'admin' and name 'ci' already exists"), "{}", stderr(&output));
+}
@@ -0,0 +1,241 @@
+#![allow(clippy::needless_return)]
+
+extern crate reqwest;
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+extern crate sonar_as_code;
+extern crate tiny_http;
+
+mod common;
+
+use std::env;
+use std::fs;
+
+use common::{quality_profile, run_with_input, setup, stderr, MockSonar};
+use sonar_as_code::sonar_client::SonarClient;
+
+const CONFIGURATION: &str = r#"
+properties:
+  - name: sonar.branch.longLivedBranches.regex
+    value: (release|master).*
+groups:
+  - name: developers
+    description: Developers
+    permissions: [codeviewer, scan, user]
+users:
+  - login: jdoe
+    name: John Doe
+    password: secret
+    passwordPolicy: createOnly
+    email: jdoe@example.com
+    scmAccounts: [jdoe, john.doe@example.com]
+    groups: [developers]
+    notifications:
+      - type: ChangesOnMyIssue
+"#;
+
+#[test]
+fn sets_up_a_fresh_server() {
+    let sonar = MockSonar::start();
+
+    let output = setup(&sonar, CONFIGURATION, &[]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sonar.property("sonar.branch.longLivedBranches.regex").as_deref(), Some("(release|master).*"));
+    assert_eq!(sonar.template_permissions("developers"), vec!["codeviewer", "scan", "user"]);
+    assert_eq!(sonar.user_groups("jdoe"), vec!["developers", "sonar-users"]);
+
+    let creation = sonar.writes().into_iter().find(|request| request.path == "/api/users/create").unwrap();
+    assert_eq!(creation.param("password"), Some("secret"));
+
+    let jdoe = sonar.sonar().get_user("jdoe").unwrap().unwrap();
+    assert_eq!(jdoe.email.as_deref(), Some("jdoe@example.com"));
+    assert_eq!(jdoe.scm_accounts, Some(vec!["jdoe".to_string(), "john.doe@example.com".to_string()]));
+
+    let notifications = sonar.sonar().get_user_notifications("jdoe").unwrap();
+    assert_eq!(notifications.iter().map(|notification| notification.to_string()).collect::<Vec<String>>(), vec!["ChangesOnMyIssue@EmailNotificationChannel"]);
+}
+
+#[test]
+fn second_run_makes_no_writes() {
+    let sonar = MockSonar::start();
+    assert!(setup(&sonar, CONFIGURATION, &[]).status.success());
+    sonar.clear_requests();
+
+    let output = setup(&sonar, CONFIGURATION, &[]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sonar.writes().is_empty(), "unexpected writes {:?}", sonar.writes());
+
+    // the state is fetched once, only notifications are read per user
+    let reads: Vec<String> = sonar.requests().iter().map(|request| request.path.to_string()).collect();
+    assert_eq!(reads, vec![
+        "/api", "/api/settings/values", "/api/user_groups/search", "/api/users/search", "/api/users/search",
+        "/api/permissions/template_groups", "/api/notifications/list",
+    ]);
+}
+
+#[test]
+fn only_sends_permissions_that_change() {
+    let sonar = MockSonar::start();
+    assert!(setup(&sonar, CONFIGURATION, &[]).status.success());
+    sonar.clear_requests();
+
+    let output = setup(&sonar, &CONFIGURATION.replace("permissions: [codeviewer, scan, user]", "permissions: [codeviewer, issueadmin, user]"), &[]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sonar.template_permissions("developers"), vec!["codeviewer", "issueadmin", "user"]);
+
+    let writes: Vec<String> = sonar.writes().iter().map(|request| format!("{} {}", request.path, request.param("permission").unwrap_or_default())).collect();
+    assert_eq!(writes, vec!["/api/permissions/remove_group_from_template scan", "/api/permissions/add_group_to_template issueadmin"]);
+}
+
+#[test]
+fn reads_the_configuration_from_the_standard_input() {
+    let sonar = MockSonar::start();
+
+    let configuration = r#"{"groups": [{"name": "developers", "description": "Developers", "permissions": ["scan"]}]}"#;
+    let output = run_with_input(&["setup", "-f", "-", "--format", "json", "-s", sonar.url(), "-a", "1"], configuration);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sonar.sonar().get_group_by_name("developers").unwrap().is_some());
+}
+
+#[test]
+fn applies_resources_concurrently() {
+    let sonar = MockSonar::start();
+
+    let output = setup(&sonar, CONFIGURATION, &["--parallelism", "4", "--rate-limit", "50"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sonar.template_permissions("developers"), vec!["codeviewer", "scan", "user"]);
+    assert_eq!(sonar.user_groups("jdoe"), vec!["developers", "sonar-users"]);
+
+    sonar.clear_requests();
+    let output = setup(&sonar, CONFIGURATION, &["--parallelism", "4"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sonar.writes().is_empty(), "unexpected writes {:?}", sonar.writes());
+}
+
+#[test]
+fn reads_every_page() {
+    let sonar = MockSonar::start();
+    sonar.set_page_size(1);
+    sonar.add_user("ldap-user", false);
+    assert!(setup(&sonar, CONFIGURATION, &[]).status.success());
+    sonar.clear_requests();
+
+    // developers gets a member, users (admin, jdoe and ldap-user) are read one page at a time
+    let output = setup(&sonar, &CONFIGURATION.replace("permissions: [codeviewer, scan, user]", "permissions: [codeviewer, scan, user]\n    members: [ldap-user, jdoe]"), &[]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sonar.user_groups("ldap-user"), vec!["developers", "sonar-users"]);
+    assert_eq!(sonar.user_groups("jdoe"), vec!["developers", "sonar-users"]);
+
+    let pages: Vec<String> = sonar.requests().iter()
+        .filter(|request| request.path == "/api/users/search" && request.param("deactivated") == Some("false"))
+        .map(|request| request.param("p").unwrap().to_string())
+        .collect();
+    assert_eq!(pages, vec!["1", "2", "3"]);
+}
+
+#[test]
+fn reports_errors_of_the_server() {
+    let sonar = MockSonar::start();
+    sonar.fail("/api/user_groups/create", 403, "Insufficient privileges");
+
+    let output = setup(&sonar, CONFIGURATION, &[]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("Error while creating group [developers] (status 403 Forbidden): Insufficient privileges"), "{}", stderr(&output));
+}
+
+#[test]
+fn keeps_going_after_errors_of_the_server() {
+    let sonar = MockSonar::start();
+    sonar.fail("/api/user_groups/create", 403, "Insufficient privileges");
+
+    let output = setup(&sonar, CONFIGURATION, &["--keep-going", "--output", "json"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(sonar.sonar().get_user("jdoe").unwrap().is_some());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["success"], json!(false));
+    // the group, its permissions and the membership of its user are skipped
+    assert_eq!(report["summary"]["failed"], json!(3));
+
+    let membership = report["resources"].as_array().unwrap().iter().find(|resource| resource["kind"] == json!("membership")).unwrap();
+    assert_eq!(membership["error"], json!("Skipped, the group [developers] cannot be applied"));
+}
+
+#[test]
+fn prunes_undeclared_groups() {
+    let sonar = MockSonar::start();
+    assert!(setup(&sonar, CONFIGURATION, &[]).status.success());
+
+    let output = setup(&sonar, &CONFIGURATION.replace("groups: [developers]", "groups: []").replace("  - name: developers\n    description: Developers\n    permissions: [codeviewer, scan, user]\n", ""), &["--prune"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let groups: Vec<String> = sonar.sonar().get_groups().unwrap().into_iter().map(|group| group.name).collect();
+    assert_eq!(groups, vec!["sonar-administrators", "sonar-users"]);
+    assert!(sonar.template_permissions("developers").is_empty());
+}
+
+#[test]
+fn restores_quality_profiles_that_differ() {
+    let sonar = MockSonar::start();
+    let file = env::temp_dir().join(format!("sonar-as-code-strict-{}.xml", std::process::id()));
+    fs::write(&file, quality_profile("Strict", "java")).unwrap();
+    let configuration = format!("qualityProfiles:\n  - file: {}\n", file.file_name().unwrap().to_string_lossy());
+
+    let output = setup(&sonar, &configuration, &[]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sonar.sonar().backup_quality_profile("java", "Strict").unwrap(), quality_profile("Strict", "java"));
+
+    // the file is relative to the configuration file, the profile is not restored again when it's the same
+    sonar.clear_requests();
+    let output = setup(&sonar, &configuration, &[]);
+    fs::remove_file(&file).unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(sonar.writes().is_empty(), "unexpected writes {:?}", sonar.writes());
+}
+
+#[test]
+fn creates_and_updates_custom_rules() {
+    let sonar = MockSonar::start();
+    let configuration = r#"
+customRules:
+  - templateKey: java:S124
+    customKey: no-todo
+    name: No TODO
+    severity: MINOR
+    markdownDescription: TODO comments must be tracked as issues
+    params:
+      regularExpression: .*TODO.*
+      message: Track it as an issue
+"#;
+
+    let output = setup(&sonar, configuration, &[]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let rule = sonar.sonar().get_rule("java:no-todo").unwrap().unwrap();
+    assert_eq!(rule.template_key.as_deref(), Some("java:S124"));
+    assert_eq!(rule.severity.as_deref(), Some("MINOR"));
+    assert_eq!(rule.param("regularExpression"), Some(".*TODO.*"));
+    assert_eq!(rule.param("message"), Some("Track it as an issue"));
+
+    sonar.clear_requests();
+    assert!(setup(&sonar, configuration, &[]).status.success());
+    assert!(sonar.writes().is_empty(), "unexpected writes {:?}", sonar.writes());
+
+    let output = setup(&sonar, &configuration.replace("Track it as an issue", "Track it; or fix it"), &[]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sonar.writes().iter().map(|request| request.path.as_str()).collect::<Vec<&str>>(), vec!["/api/rules/update"]);
+    assert_eq!(sonar.sonar().get_rule("java:no-todo").unwrap().unwrap().param("message"), Some("Track it; or fix it"));
+}